@@ -0,0 +1,530 @@
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::env;
+use std::ffi::CString;
+use std::fs;
+use std::os::raw::{c_int, c_uint, c_ulong};
+use std::path::PathBuf;
+
+use log::{info, warn};
+use serde::Deserialize;
+use x11::xlib::*;
+
+#[derive(Deserialize)]
+#[serde(default)]
+pub struct Config {
+    pub border_width: c_uint,
+    pub focused_border_color: Color,
+    pub unfocused_border_color: Color,
+    // Border of unfocused windows that set the urgency hint.
+    pub urgent_border_color: Color,
+    pub background_color: Color,
+    // Show the root window name as status text in a bar at the top of the first monitor.
+    pub show_bar: bool,
+    // Accept commands on `$XDG_RUNTIME_DIR/simple-wm-<display>.sock`.
+    pub ipc: bool,
+    // Empty space around each tiled window.
+    pub gap: c_int,
+    // Empty space between the monitor edges and tiled, maximized or snapped windows.
+    pub outer_gap: c_int,
+    // Reopen windows of the same `WM_CLASS` where they were last placed.
+    pub remember_geometry: bool,
+    // Move the pointer to windows focused from the keyboard.
+    pub warp_pointer: bool,
+    // With a compositor running, give frames a 32-bit visual so that colors with an alpha byte,
+    // e.g. `"#80ff0000"`, are translucent.
+    pub argb_frames: bool,
+    // Distance within which dragged windows snap to monitor and window edges.
+    pub snap_threshold: c_int,
+    // Also snap the edges of windows resized with the mouse.
+    pub snap_resize: bool,
+    // Cell size that dragged windows snap their position and size to, 1 to disable.
+    pub grid_size: c_int,
+    // Pixels moved or resized per keypress.
+    pub keyboard_step: c_int,
+    // Used for mouse move/resize and as `Mod` in key bindings.
+    pub mod_key: ModKey,
+    // Merged over the default bindings, e.g. `"Mod+Return" = "spawn xterm"`.
+    pub keybindings: HashMap<String, String>,
+    // Names of the workspaces from the first one, published for panels.
+    pub workspace_names: Vec<String>,
+    // Entries of the menu opened by right-clicking the desktop.
+    pub menu: Vec<MenuEntry>,
+    // Applied in order to new windows, later matches taking precedence.
+    pub rules: Vec<Rule>,
+    // Commands run once when the window manager starts, but not on reload.
+    pub autostart: Vec<String>,
+}
+
+// e.g. `[[menu]]` with `label = "Terminal"` and `action = "spawn xterm"`.
+#[derive(Deserialize)]
+pub struct MenuEntry {
+    pub label: String,
+    pub action: String,
+}
+
+// e.g. `[[rules]]` with `class = "mpv"` and `floating = true`.
+#[derive(Deserialize)]
+pub struct Rule {
+    // Matches the `WM_CLASS` class, ignoring case.
+    pub class: Option<String>,
+    // Matches when contained in the window title.
+    pub title: Option<String>,
+    // Starting from 1.
+    pub workspace: Option<usize>,
+    pub floating: Option<bool>,
+    pub border: Option<bool>,
+}
+
+#[derive(Clone, Debug)]
+pub enum Action {
+    Close,
+    Kill,
+    Maximize,
+    Lower,
+    Minimize,
+    NextWindow,
+    ToggleLayout,
+    CycleLayout,
+    AdjustMasterRatio(f32),
+    AdjustOpacity(f32),
+    ToggleFloating,
+    ToggleBorder,
+    ToggleAbove,
+    ToggleSticky,
+    SwapMaster,
+    Workspace(usize),
+    MoveToWorkspace(usize),
+    SendToMonitor(isize),
+    Move(c_int, c_int),
+    Resize(c_int, c_int),
+    Spawn(String),
+    Reload,
+    DumpState,
+    Quit,
+}
+
+#[derive(Clone, Copy, Deserialize)]
+#[serde(try_from = "String")]
+pub struct Color(pub c_ulong);
+
+#[derive(Clone, Copy, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ModKey {
+    Alt,
+    Super,
+    Ctrl,
+}
+
+impl ModKey {
+    pub fn mask(self) -> c_uint {
+        match self {
+            ModKey::Alt => Mod1Mask,
+            ModKey::Super => Mod4Mask,
+            ModKey::Ctrl => ControlMask,
+        }
+    }
+}
+
+impl Color {
+    // The pixel on a 32-bit visual, opaque unless an alpha byte was given.
+    pub fn argb(self) -> c_ulong {
+        if self.0 > 0xffffff {
+            self.0
+        } else {
+            self.0 | 0xff000000
+        }
+    }
+}
+
+impl TryFrom<String> for Color {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        let hex = value.trim_start_matches('#').trim_start_matches("0x");
+        c_ulong::from_str_radix(hex, 16)
+            .map(Color)
+            .map_err(|_| format!("invalid color: \"{}\"", value))
+    }
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            border_width: 3,
+            focused_border_color: Color(0x00ff00),
+            unfocused_border_color: Color(0xff0000),
+            urgent_border_color: Color(0xffff00),
+            background_color: Color(0x0000ff),
+            show_bar: true,
+            ipc: true,
+            gap: 0,
+            outer_gap: 0,
+            remember_geometry: false,
+            warp_pointer: false,
+            argb_frames: false,
+            snap_threshold: 15,
+            snap_resize: false,
+            grid_size: 1,
+            keyboard_step: 20,
+            mod_key: ModKey::Alt,
+            keybindings: HashMap::new(),
+            workspace_names: Vec::new(),
+            menu: vec![
+                MenuEntry {
+                    label: "Terminal".into(),
+                    action: "spawn xterm".into(),
+                },
+                MenuEntry {
+                    label: "Reload".into(),
+                    action: "reload".into(),
+                },
+                MenuEntry {
+                    label: "Quit".into(),
+                    action: "quit".into(),
+                },
+            ],
+            rules: Vec::new(),
+            autostart: Vec::new(),
+        }
+    }
+}
+
+impl Config {
+    pub fn load() -> Self {
+        match Self::try_load() {
+            Ok(config) => config,
+            Err(e) => {
+                warn!("{}, using defaults", e);
+                Self::default()
+            }
+        }
+    }
+
+    pub fn try_load() -> Result<Self, String> {
+        let path = Self::path().ok_or("Cannot locate the config directory")?;
+        let content = fs::read_to_string(&path)
+            .map_err(|e| format!("Cannot read \"{}\": {}", path.display(), e))?;
+        let config = toml::from_str(&content)
+            .map_err(|e| format!("Cannot parse \"{}\": {}", path.display(), e))?;
+        info!("Loaded config: \"{}\"", path.display());
+        Ok(config)
+    }
+
+    pub fn keybindings(&self) -> Vec<(c_uint, KeySym, Action)> {
+        let mut bindings: Vec<(String, String)> = vec![
+            ("Mod+F4".into(), "close".into()),
+            ("Mod+Shift+q".into(), "kill".into()),
+            ("Mod+Return".into(), "spawn xterm".into()),
+            ("Mod+Shift+Return".into(), "swap_master".into()),
+            ("Mod+m".into(), "maximize".into()),
+            ("Mod+Shift+l".into(), "lower".into()),
+            ("Mod+n".into(), "minimize".into()),
+            ("Mod+Tab".into(), "next_window".into()),
+            ("Mod+t".into(), "toggle_layout".into()),
+            ("Mod+Shift+t".into(), "cycle_layout".into()),
+            ("Mod+h".into(), "adjust_master_ratio -0.05".into()),
+            ("Mod+l".into(), "adjust_master_ratio 0.05".into()),
+            ("Mod+minus".into(), "adjust_opacity -0.1".into()),
+            ("Mod+equal".into(), "adjust_opacity 0.1".into()),
+            ("Mod+space".into(), "toggle_floating".into()),
+            ("Mod+b".into(), "toggle_border".into()),
+            ("Mod+a".into(), "toggle_above".into()),
+            ("Mod+s".into(), "toggle_sticky".into()),
+            ("Mod+Shift+period".into(), "send_to_monitor next".into()),
+            ("Mod+Shift+comma".into(), "send_to_monitor prev".into()),
+        ];
+        for &(key, direction) in &[
+            ("Left", "left"),
+            ("Right", "right"),
+            ("Up", "up"),
+            ("Down", "down"),
+        ] {
+            bindings.push((format!("Mod+Shift+{}", key), format!("move {}", direction)));
+            bindings.push((format!("Mod+Ctrl+{}", key), format!("resize {}", direction)));
+        }
+        for i in 1..=9 {
+            bindings.push((format!("Mod+{}", i), format!("workspace {}", i)));
+            bindings.push((
+                format!("Mod+Shift+{}", i),
+                format!("move_to_workspace {}", i),
+            ));
+        }
+        bindings.extend(
+            self.keybindings
+                .iter()
+                .map(|(key, action)| (key.clone(), action.clone())),
+        );
+
+        let mut parsed: Vec<(c_uint, KeySym, Action)> = Vec::new();
+        for (key, action) in bindings {
+            let (modifiers, keysym) = match parse_key(&key, self.mod_key.mask()) {
+                Some(key) => key,
+                None => {
+                    warn!("Invalid key \"{}\"", key);
+                    continue;
+                }
+            };
+            let action = match Action::parse(&action) {
+                Some(action) => action,
+                None => {
+                    warn!("Invalid action \"{}\" for \"{}\"", action, key);
+                    continue;
+                }
+            };
+            parsed.retain(|&(m, k, _)| (m, k) != (modifiers, keysym));
+            parsed.push((modifiers, keysym, action));
+        }
+        parsed
+    }
+
+    // Whether the last matching rule that sets `border` wants one.
+    pub fn border_rule(&self, class: Option<&str>, title: &str) -> Option<bool> {
+        self.rules
+            .iter()
+            .rev()
+            .filter(|rule| rule.matches(class, title))
+            .find_map(|rule| rule.border)
+    }
+
+    pub fn dir() -> Option<PathBuf> {
+        let config_dir = match env::var_os("XDG_CONFIG_HOME") {
+            Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+            _ => PathBuf::from(env::var_os("HOME")?).join(".config"),
+        };
+        Some(config_dir.join("simple-wm"))
+    }
+
+    fn path() -> Option<PathBuf> {
+        Some(Self::dir()?.join("config.toml"))
+    }
+}
+
+impl Rule {
+    pub fn matches(&self, class: Option<&str>, title: &str) -> bool {
+        let class_matches = match (&self.class, class) {
+            (Some(pattern), Some(class)) => pattern.eq_ignore_ascii_case(class),
+            (Some(_), None) => false,
+            (None, _) => true,
+        };
+        let title_matches = self
+            .title
+            .as_ref()
+            .is_none_or(|pattern| title.contains(pattern.as_str()));
+        class_matches && title_matches
+    }
+}
+
+impl Action {
+    pub fn parse(s: &str) -> Option<Self> {
+        let mut parts = s.trim().splitn(2, ' ');
+        let name = parts.next()?;
+        let arg = parts.next().map(str::trim);
+        let workspace = || match arg?.parse::<usize>() {
+            Ok(n) if n >= 1 => Some(n - 1),
+            _ => None,
+        };
+        let direction = || match arg? {
+            "left" => Some((-1, 0)),
+            "right" => Some((1, 0)),
+            "up" => Some((0, -1)),
+            "down" => Some((0, 1)),
+            _ => None,
+        };
+
+        match name {
+            "close" => Some(Action::Close),
+            "kill" => Some(Action::Kill),
+            "maximize" => Some(Action::Maximize),
+            "lower" => Some(Action::Lower),
+            "minimize" => Some(Action::Minimize),
+            "next_window" => Some(Action::NextWindow),
+            "toggle_layout" => Some(Action::ToggleLayout),
+            "cycle_layout" => Some(Action::CycleLayout),
+            "adjust_master_ratio" => arg?.parse().ok().map(Action::AdjustMasterRatio),
+            "adjust_opacity" => arg?.parse().ok().map(Action::AdjustOpacity),
+            "toggle_floating" => Some(Action::ToggleFloating),
+            "toggle_border" => Some(Action::ToggleBorder),
+            "toggle_above" => Some(Action::ToggleAbove),
+            "toggle_sticky" => Some(Action::ToggleSticky),
+            "swap_master" => Some(Action::SwapMaster),
+            "workspace" => workspace().map(Action::Workspace),
+            "move_to_workspace" => workspace().map(Action::MoveToWorkspace),
+            "send_to_monitor" => match arg? {
+                "next" => Some(Action::SendToMonitor(1)),
+                "prev" => Some(Action::SendToMonitor(-1)),
+                _ => None,
+            },
+            "move" => direction().map(|(dx, dy)| Action::Move(dx, dy)),
+            "resize" => direction().map(|(dx, dy)| Action::Resize(dx, dy)),
+            "reload" => Some(Action::Reload),
+            "dump_state" => Some(Action::DumpState),
+            "quit" => Some(Action::Quit),
+            "spawn" => arg
+                .filter(|cmd| !cmd.is_empty())
+                .map(|cmd| Action::Spawn(cmd.into())),
+            _ => None,
+        }
+    }
+}
+
+fn parse_key(s: &str, mod_mask: c_uint) -> Option<(c_uint, KeySym)> {
+    let mut parts: Vec<&str> = s.split('+').map(str::trim).collect();
+    let key = parts.pop()?;
+
+    let mut modifiers = 0;
+    for part in parts {
+        modifiers |= match part.to_lowercase().as_str() {
+            "mod" => mod_mask,
+            "shift" => ShiftMask,
+            "ctrl" | "control" => ControlMask,
+            "mod1" | "alt" => Mod1Mask,
+            "mod4" | "super" => Mod4Mask,
+            _ => return None,
+        };
+    }
+
+    let name = CString::new(key).ok()?;
+    let keysym = unsafe { XStringToKeysym(name.as_ptr()) };
+    if keysym == NoSymbol as KeySym {
+        return None;
+    }
+    Some((modifiers, keysym))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_key_modifiers() {
+        let (modifiers, keysym) = parse_key("Mod+Shift+Return", Mod4Mask).unwrap();
+        assert_eq!(modifiers, Mod4Mask | ShiftMask);
+        assert_eq!(keysym, x11::keysym::XK_Return as KeySym);
+
+        let (modifiers, _) = parse_key("ctrl + alt + F4", Mod1Mask).unwrap();
+        assert_eq!(modifiers, ControlMask | Mod1Mask);
+        let (modifiers, _) = parse_key("Super+a", Mod1Mask).unwrap();
+        assert_eq!(modifiers, Mod4Mask);
+        let (modifiers, _) = parse_key("q", Mod1Mask).unwrap();
+        assert_eq!(modifiers, 0);
+    }
+
+    #[test]
+    fn parse_key_invalid() {
+        assert!(parse_key("Hyper+a", Mod1Mask).is_none());
+        assert!(parse_key("Mod+NoSuchKey", Mod1Mask).is_none());
+        assert!(parse_key("Mod+", Mod1Mask).is_none());
+    }
+
+    #[test]
+    fn parse_actions() {
+        assert!(matches!(Action::parse("close"), Some(Action::Close)));
+        assert!(matches!(
+            Action::parse(" workspace 3 "),
+            Some(Action::Workspace(2))
+        ));
+        assert!(matches!(
+            Action::parse("move_to_workspace 1"),
+            Some(Action::MoveToWorkspace(0))
+        ));
+        assert!(matches!(
+            Action::parse("move left"),
+            Some(Action::Move(-1, 0))
+        ));
+        assert!(matches!(
+            Action::parse("resize down"),
+            Some(Action::Resize(0, 1))
+        ));
+        assert!(matches!(
+            Action::parse("send_to_monitor prev"),
+            Some(Action::SendToMonitor(-1))
+        ));
+        assert!(matches!(
+            Action::parse("adjust_master_ratio -0.05"),
+            Some(Action::AdjustMasterRatio(ratio)) if ratio == -0.05
+        ));
+        assert!(matches!(
+            Action::parse("spawn xterm -e top"),
+            Some(Action::Spawn(cmd)) if cmd == "xterm -e top"
+        ));
+    }
+
+    #[test]
+    fn parse_invalid_actions() {
+        assert!(Action::parse("").is_none());
+        assert!(Action::parse("frobnicate").is_none());
+        assert!(Action::parse("workspace").is_none());
+        assert!(Action::parse("workspace 0").is_none());
+        assert!(Action::parse("workspace -1").is_none());
+        assert!(Action::parse("workspace two").is_none());
+        assert!(Action::parse("move sideways").is_none());
+        assert!(Action::parse("send_to_monitor 2").is_none());
+        assert!(Action::parse("adjust_opacity").is_none());
+        assert!(Action::parse("adjust_opacity more").is_none());
+        assert!(Action::parse("spawn").is_none());
+        assert!(Action::parse("spawn   ").is_none());
+    }
+
+    #[test]
+    fn default_terminal_binding() {
+        let bindings = Config::default().keybindings();
+        let return_key = x11::keysym::XK_Return as KeySym;
+        let action = |modifiers: c_uint| {
+            bindings
+                .iter()
+                .find(|&&(m, keysym, _)| m == modifiers && keysym == return_key)
+                .map(|(_, _, action)| action.clone())
+        };
+        assert!(matches!(action(Mod1Mask), Some(Action::Spawn(cmd)) if cmd == "xterm"));
+        assert!(matches!(
+            action(Mod1Mask | ShiftMask),
+            Some(Action::SwapMaster)
+        ));
+    }
+
+    #[test]
+    fn parse_colors() {
+        let color = |s: &str| Color::try_from(s.to_string()).map(|color| color.0);
+        assert_eq!(color("#00ff00"), Ok(0x00ff00));
+        assert_eq!(color("0xff0000"), Ok(0xff0000));
+        assert_eq!(color("0000ff"), Ok(0x0000ff));
+        assert_eq!(color("#80000000"), Ok(0x80000000));
+        assert!(color("green").is_err());
+        assert!(color("#").is_err());
+    }
+
+    #[test]
+    fn rule_matches() {
+        let rule = |class: Option<&str>, title: Option<&str>| Rule {
+            class: class.map(String::from),
+            title: title.map(String::from),
+            workspace: None,
+            floating: None,
+            border: None,
+        };
+
+        let mpv = rule(Some("mpv"), None);
+        assert!(mpv.matches(Some("mpv"), ""));
+        assert!(mpv.matches(Some("MPV"), "video.mkv"));
+        assert!(!mpv.matches(Some("mpvx"), ""));
+        assert!(!mpv.matches(None, "mpv"));
+
+        let title = rule(None, Some("Preferences"));
+        assert!(title.matches(None, "Firefox Preferences"));
+        assert!(!title.matches(Some("Firefox"), "firefox preferences"));
+
+        let both = rule(Some("Firefox"), Some("Preferences"));
+        assert!(both.matches(Some("firefox"), "Preferences"));
+        assert!(!both.matches(Some("firefox"), "Mozilla Firefox"));
+
+        assert!(rule(None, None).matches(None, ""));
+    }
+
+    #[test]
+    fn argb_alpha() {
+        assert_eq!(Color(0x00ff00).argb(), 0xff00ff00);
+        assert_eq!(Color(0x80ff0000).argb(), 0x80ff0000);
+        assert_eq!(Color(0).argb(), 0xff000000);
+    }
+}
@@ -0,0 +1,141 @@
+use std::env;
+use std::fs;
+use std::io::{Read, Write};
+use std::os::raw::c_int;
+use std::os::unix::fs::MetadataExt;
+use std::os::unix::io::AsRawFd;
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+use log::{info, warn};
+
+// Time spent on commands per wakeup, so that slow clients cannot hold up X events.
+const BUDGET: Duration = Duration::from_millis(100);
+// Longest accepted command line.
+const MAX_LINE: usize = 4096;
+
+// Newline-delimited commands are read from `$XDG_RUNTIME_DIR/simple-wm-<display>.sock`.
+pub struct Ipc {
+    listener: UnixListener,
+    path: PathBuf,
+    // Device and inode of the socket, so that one bound by another instance is left alone.
+    id: (u64, u64),
+}
+
+impl Ipc {
+    pub fn bind(display: &str) -> Option<Self> {
+        let name = format!("simple-wm-{}.sock", display.replace('/', "_"));
+        let path = match env::var_os("XDG_RUNTIME_DIR") {
+            Some(dir) if !dir.is_empty() => PathBuf::from(dir).join(name),
+            _ => {
+                warn!("XDG_RUNTIME_DIR is not set, IPC disabled");
+                return None;
+            }
+        };
+
+        let _ = fs::remove_file(&path);
+        let listener = match UnixListener::bind(&path) {
+            Ok(listener) => listener,
+            Err(e) => {
+                warn!("Cannot bind \"{}\": {}, IPC disabled", path.display(), e);
+                return None;
+            }
+        };
+        if let Err(e) = listener.set_nonblocking(true) {
+            warn!("Cannot set \"{}\" non-blocking: {}", path.display(), e);
+            return None;
+        }
+
+        let id = match fs::metadata(&path) {
+            Ok(metadata) => (metadata.dev(), metadata.ino()),
+            Err(e) => {
+                warn!("Cannot stat \"{}\": {}", path.display(), e);
+                return None;
+            }
+        };
+
+        info!("Listening on \"{}\"", path.display());
+        Some(Self { listener, path, id })
+    }
+
+    pub fn fd(&self) -> c_int {
+        self.listener.as_raw_fd()
+    }
+
+    // Accept pending connections and pass each command line to `handle`, whose result is sent back.
+    // Connections still open when the budget runs out are dropped, the rest wait for the next call.
+    pub fn accept(&self, mut handle: impl FnMut(&str) -> Result<(), String>) {
+        let deadline = Instant::now() + BUDGET;
+        while Instant::now() < deadline {
+            let stream = match self.listener.accept() {
+                Ok((stream, _)) => stream,
+                Err(_) => break,
+            };
+            if stream.set_nonblocking(false).is_ok() {
+                serve(&stream, deadline, &mut handle);
+            }
+        }
+    }
+}
+
+fn serve(
+    mut stream: &UnixStream,
+    deadline: Instant,
+    handle: &mut impl FnMut(&str) -> Result<(), String>,
+) {
+    let mut pending = Vec::new();
+    let mut buf = [0; 1024];
+    loop {
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        if remaining.is_zero()
+            || stream.set_read_timeout(Some(remaining)).is_err()
+            || stream.set_write_timeout(Some(remaining)).is_err()
+        {
+            return;
+        }
+        let eof = match stream.read(&mut buf) {
+            Ok(0) => true,
+            Ok(n) => {
+                pending.extend_from_slice(&buf[..n]);
+                false
+            }
+            Err(_) => return,
+        };
+
+        // The last line may lack its newline.
+        let mut lines: Vec<Vec<u8>> = Vec::new();
+        while let Some(end) = pending.iter().position(|&b| b == b'\n') {
+            lines.push(pending.drain(..=end).collect());
+        }
+        if eof {
+            lines.push(pending.split_off(0));
+        }
+        for line in lines {
+            let line = String::from_utf8_lossy(&line);
+            if line.trim().is_empty() {
+                continue;
+            }
+            let reply = match handle(line.trim()) {
+                Ok(()) => "ok\n".to_string(),
+                Err(e) => format!("error: {}\n", e),
+            };
+            if stream.write_all(reply.as_bytes()).is_err() {
+                return;
+            }
+        }
+        if eof || pending.len() > MAX_LINE {
+            return;
+        }
+    }
+}
+
+impl Drop for Ipc {
+    fn drop(&mut self) {
+        if let Ok(metadata) = fs::metadata(&self.path) {
+            if (metadata.dev(), metadata.ino()) == self.id {
+                let _ = fs::remove_file(&self.path);
+            }
+        }
+    }
+}
@@ -0,0 +1,4166 @@
+use std::cmp::{max, min};
+use std::collections::{HashMap, HashSet};
+use std::default::Default;
+use std::ffi::{CStr, CString};
+use std::io;
+use std::mem::{take, zeroed, MaybeUninit};
+use std::os::raw::{c_char, c_int, c_long, c_uint, c_ulong, c_void};
+use std::os::unix::process::CommandExt;
+use std::process::{self, Command, Stdio};
+use std::ptr::{null, null_mut};
+use std::slice;
+use std::sync::atomic::{AtomicBool, AtomicI32, Ordering};
+use std::thread;
+use std::time::Duration;
+
+use log::{debug, error, info, warn};
+#[cfg(feature = "xinerama")]
+use x11::xinerama::*;
+use x11::xlib::*;
+
+use config::{Action, Color, Config};
+use ipc::Ipc;
+use placements::Placements;
+
+mod config;
+mod ipc;
+mod placements;
+#[cfg(feature = "xrandr")]
+use x11::xrandr::*;
+
+static WM_DETECTED: AtomicBool = AtomicBool::new(false);
+static QUIT: AtomicBool = AtomicBool::new(false);
+static RELOAD: AtomicBool = AtomicBool::new(false);
+static SIGNAL_PIPE: AtomicI32 = AtomicI32::new(-1);
+
+const TITLE_HEIGHT: c_int = 18;
+const TITLE_FONT: &str = "fixed";
+const TITLE_COLOR: c_ulong = 0xffffff;
+const BAR_HEIGHT: c_int = TITLE_HEIGHT;
+const OVERLAY_PADDING: c_int = 8;
+const MIN_OPACITY: f32 = 0.1;
+const FOCUS_FOLLOWS_MOUSE: bool = true;
+const WORKSPACE_COUNT: usize = 9;
+// Pixels of a frame that configure requests must leave inside the usable area.
+const MIN_VISIBLE: c_int = 32;
+pub const WM_NAME: &str = "simple-wm";
+// CapsLock and NumLock, which must not stop grabs from matching.
+const LOCK_MASKS: [c_uint; 4] = [0, LockMask, Mod2Mask, LockMask | Mod2Mask];
+
+// ICCCM `WM_STATE` values, which Xlib does not define.
+const NORMAL_STATE: c_int = 1;
+const ICONIC_STATE: c_int = 3;
+// Non-blocking `XEventsQueued()` mode, which the x11 crate does not define.
+const QUEUED_AFTER_READING: c_int = 1;
+
+// `_MOTIF_WM_HINTS` flag telling that the decorations field is set.
+const MWM_HINTS_DECORATIONS: c_ulong = 1 << 1;
+
+pub struct WindowManager {
+    config: Config,
+    display: *mut Display,
+    root: Window,
+    clients: HashMap<Window, Client>,
+    frame_to_client: HashMap<Window, Window>,
+    drag: DragInfo,
+    keybindings: Vec<KeyBinding>,
+    focused: Option<Window>,
+    focus_stack: Vec<Window>,
+    // Clients in the order they were framed, published as `_NET_CLIENT_LIST`.
+    client_list: Vec<Window>,
+    // Frames from bottom to top.
+    stacking: Vec<Window>,
+    minimized: HashSet<Window>,
+    cycle_index: Option<usize>,
+    // Windows of each workspace in tiling order, the first one being the master.
+    workspaces: Vec<Vec<Window>>,
+    current_workspace: usize,
+    layouts: Vec<Layout>,
+    master_ratios: Vec<f32>,
+    monitors: Vec<MonitorInfo>,
+    #[cfg(feature = "xrandr")]
+    randr_event_base: Option<c_int>,
+    docks: HashMap<Window, Struts>,
+    struts: Struts,
+    font: *mut XFontStruct,
+    gc: GC,
+    argb_visual: Option<ArgbVisual>,
+    check_window: Window,
+    // Frames and helper windows created by the window manager, which are never framed.
+    own_windows: HashSet<Window>,
+    signal_fd: c_int,
+    server_grabs: u32,
+    ipc: Option<Ipc>,
+    placements: Placements,
+    bar: Window,
+    status: String,
+    // The list of windows shown while cycling focus, 0 when hidden.
+    cycle_overlay: Window,
+    // The desktop menu, 0 when hidden.
+    menu: Window,
+    menu_selected: Option<usize>,
+    // The button that opened the menu is still pressed.
+    menu_opening: bool,
+    wm_protocols: Atom,
+    wm_delete_window: Atom,
+    wm_take_focus: Atom,
+    wm_state: Atom,
+    wm_change_state: Atom,
+    wm_selection: Atom,
+    manager: Atom,
+    net_wm_cm: Atom,
+    utf8_string: Atom,
+    net_supported: Atom,
+    net_supporting_wm_check: Atom,
+    net_wm_name: Atom,
+    net_active_window: Atom,
+    net_client_list: Atom,
+    net_wm_window_type: Atom,
+    net_wm_window_type_dock: Atom,
+    net_wm_window_type_dialog: Atom,
+    net_wm_window_type_splash: Atom,
+    net_wm_strut: Atom,
+    net_wm_strut_partial: Atom,
+    net_wm_state: Atom,
+    net_wm_state_fullscreen: Atom,
+    net_wm_state_above: Atom,
+    net_wm_state_below: Atom,
+    net_wm_state_sticky: Atom,
+    net_wm_state_maximized_vert: Atom,
+    net_wm_state_maximized_horz: Atom,
+    net_wm_state_demands_attention: Atom,
+    net_frame_extents: Atom,
+    net_wm_window_opacity: Atom,
+    net_desktop_names: Atom,
+    net_number_of_desktops: Atom,
+    net_current_desktop: Atom,
+    net_wm_desktop: Atom,
+    motif_wm_hints: Atom,
+}
+
+struct Client {
+    frame: Window,
+    title: String,
+    border_width: c_uint,
+    borderless: bool,
+    // Zero for splash screens and windows that draw their own decorations.
+    title_height: c_int,
+    size_hints: XSizeHints,
+    workspace: usize,
+    floating: bool,
+    window_type: WindowType,
+    class: Option<String>,
+    // The frame has a 32-bit visual.
+    argb: bool,
+    opacity: f32,
+    // The client set the urgency hint and was not focused since.
+    urgent: bool,
+    // Kept above or below other windows.
+    above: bool,
+    below: bool,
+    sticky: bool,
+    // Frame geometry as last reported by ConfigureNotify.
+    geometry: Geometry,
+    saved_geometry: Option<Geometry>,
+    // Geometry before the window was snapped to half of the screen.
+    snapped: Option<Geometry>,
+    fullscreen: Option<Geometry>,
+    transient_for: Option<Window>,
+    // UnmapNotify events caused by the window manager itself, skipped in `unmap_notify()`.
+    ignore_unmaps: u32,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Geometry {
+    x: c_int,
+    y: c_int,
+    width: c_int,
+    height: c_int,
+}
+
+struct MonitorInfo {
+    name: String,
+    geometry: Geometry,
+}
+
+struct ArgbVisual {
+    visual: *mut Visual,
+    colormap: Colormap,
+    gc: GC,
+}
+
+struct KeyBinding {
+    modifiers: c_uint,
+    keycode: c_uint,
+    action: Action,
+}
+
+#[derive(Default)]
+struct DragInfo {
+    window: Option<Window>,
+    button: c_uint,
+    moved: bool,
+    start_pos: (c_int, c_int),
+    start_frame_pos: (c_int, c_int),
+    start_frame_size: (c_int, c_int),
+}
+
+// Reserved space on the (left, right, top, bottom) screen edges.
+type Struts = (c_int, c_int, c_int, c_int);
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum WindowType {
+    Normal,
+    Dock,
+    Dialog,
+    Splash,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Layout {
+    Floating,
+    Tiling,
+    Monocle,
+    Grid,
+}
+
+// `_NET_WM_STATE` states that can be requested by clients.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum NetState {
+    Fullscreen,
+    Above,
+    Below,
+    Sticky,
+    // Both `_NET_WM_STATE_MAXIMIZED_VERT` and `_NET_WM_STATE_MAXIMIZED_HORZ`.
+    Maximized,
+}
+
+impl Geometry {
+    fn contains(&self, x: c_int, y: c_int) -> bool {
+        x >= self.x && x < self.x + self.width && y >= self.y && y < self.y + self.height
+    }
+}
+
+impl Client {
+    fn constrain_size(&self, width: c_int, height: c_int) -> (c_int, c_int) {
+        constrain_size(&self.size_hints, width, height)
+    }
+}
+
+// Apply the `WM_NORMAL_HINTS` resize increments and size limits to a client size.
+fn constrain_size(hints: &XSizeHints, width: c_int, height: c_int) -> (c_int, c_int) {
+    let (mut width, mut height) = (width, height);
+
+    if hints.flags & PResizeInc != 0 {
+        let base = if hints.flags & PBaseSize != 0 {
+            (hints.base_width, hints.base_height)
+        } else if hints.flags & PMinSize != 0 {
+            (hints.min_width, hints.min_height)
+        } else {
+            (0, 0)
+        };
+        if hints.width_inc > 0 && width > base.0 {
+            width = base.0 + (width - base.0) / hints.width_inc * hints.width_inc;
+        }
+        if hints.height_inc > 0 && height > base.1 {
+            height = base.1 + (height - base.1) / hints.height_inc * hints.height_inc;
+        }
+    }
+    if hints.flags & PMinSize != 0 {
+        width = max(width, hints.min_width);
+        height = max(height, hints.min_height);
+    }
+    if hints.flags & PMaxSize != 0 {
+        if hints.max_width > 0 {
+            width = min(width, hints.max_width);
+        }
+        if hints.max_height > 0 {
+            height = min(height, hints.max_height);
+        }
+    }
+
+    (width, height)
+}
+
+impl WindowManager {
+    pub fn new(display_name: Option<&str>) -> Self {
+        let display_name = display_name.map(|name| CString::new(name).unwrap());
+        let display =
+            unsafe { XOpenDisplay(display_name.as_ref().map_or(null(), |name| name.as_ptr())) };
+        if display.is_null() {
+            panic!("`XOpenDisplay()` failed!");
+        } else {
+            // The display string is owned by Xlib, so borrow it with `CStr` rather than taking
+            // ownership with `CString::from_raw`, which would free it on drop.
+            info!(
+                "Open display: \"{}\"",
+                unsafe { CStr::from_ptr(XDisplayString(display)) }
+                    .to_str()
+                    .unwrap_or("`CStr::to_str()` error!")
+            );
+        }
+
+        let intern_atom = |name: &str| unsafe {
+            let name = CString::new(name).unwrap();
+            XInternAtom(display, name.as_ptr(), 0)
+        };
+
+        let root = unsafe { XDefaultRootWindow(display) };
+        let font = unsafe {
+            let name = CString::new(TITLE_FONT).unwrap();
+            XLoadQueryFont(display, name.as_ptr())
+        };
+        if font.is_null() {
+            warn!("Failed to load font \"{}\"", TITLE_FONT);
+        }
+        let gc = unsafe {
+            let gc = XCreateGC(display, root, 0, null_mut());
+            XSetForeground(display, gc, TITLE_COLOR);
+            if !font.is_null() {
+                XSetFont(display, gc, (*font).fid);
+            }
+            gc
+        };
+
+        let mut wm = Self {
+            config: Config::load(),
+            display,
+            root,
+            clients: HashMap::new(),
+            frame_to_client: HashMap::new(),
+            drag: DragInfo::default(),
+            keybindings: Vec::new(),
+            focused: None,
+            focus_stack: Vec::new(),
+            client_list: Vec::new(),
+            stacking: Vec::new(),
+            minimized: HashSet::new(),
+            cycle_index: None,
+            workspaces: vec![Vec::new(); WORKSPACE_COUNT],
+            current_workspace: 0,
+            layouts: vec![Layout::Floating; WORKSPACE_COUNT],
+            master_ratios: vec![0.5; WORKSPACE_COUNT],
+            monitors: Vec::new(),
+            #[cfg(feature = "xrandr")]
+            randr_event_base: None,
+            docks: HashMap::new(),
+            struts: (0, 0, 0, 0),
+            font,
+            gc,
+            argb_visual: None,
+            check_window: unsafe { XCreateSimpleWindow(display, root, -1, -1, 1, 1, 0, 0, 0) },
+            own_windows: HashSet::new(),
+            bar: 0,
+            signal_fd: -1,
+            server_grabs: 0,
+            ipc: None,
+            placements: Placements::load(),
+            status: String::new(),
+            cycle_overlay: 0,
+            menu: 0,
+            menu_selected: None,
+            menu_opening: false,
+            wm_protocols: intern_atom("WM_PROTOCOLS"),
+            wm_delete_window: intern_atom("WM_DELETE_WINDOW"),
+            wm_take_focus: intern_atom("WM_TAKE_FOCUS"),
+            wm_state: intern_atom("WM_STATE"),
+            wm_change_state: intern_atom("WM_CHANGE_STATE"),
+            wm_selection: intern_atom(&format!("WM_S{}", unsafe { XDefaultScreen(display) })),
+            manager: intern_atom("MANAGER"),
+            net_wm_cm: intern_atom(&format!("_NET_WM_CM_S{}", unsafe {
+                XDefaultScreen(display)
+            })),
+            utf8_string: intern_atom("UTF8_STRING"),
+            net_supported: intern_atom("_NET_SUPPORTED"),
+            net_supporting_wm_check: intern_atom("_NET_SUPPORTING_WM_CHECK"),
+            net_wm_name: intern_atom("_NET_WM_NAME"),
+            net_active_window: intern_atom("_NET_ACTIVE_WINDOW"),
+            net_client_list: intern_atom("_NET_CLIENT_LIST"),
+            net_wm_window_type: intern_atom("_NET_WM_WINDOW_TYPE"),
+            net_wm_window_type_dock: intern_atom("_NET_WM_WINDOW_TYPE_DOCK"),
+            net_wm_window_type_dialog: intern_atom("_NET_WM_WINDOW_TYPE_DIALOG"),
+            net_wm_window_type_splash: intern_atom("_NET_WM_WINDOW_TYPE_SPLASH"),
+            net_wm_strut: intern_atom("_NET_WM_STRUT"),
+            net_wm_strut_partial: intern_atom("_NET_WM_STRUT_PARTIAL"),
+            net_wm_state: intern_atom("_NET_WM_STATE"),
+            net_wm_state_fullscreen: intern_atom("_NET_WM_STATE_FULLSCREEN"),
+            net_wm_state_above: intern_atom("_NET_WM_STATE_ABOVE"),
+            net_wm_state_below: intern_atom("_NET_WM_STATE_BELOW"),
+            net_wm_state_sticky: intern_atom("_NET_WM_STATE_STICKY"),
+            net_wm_state_maximized_vert: intern_atom("_NET_WM_STATE_MAXIMIZED_VERT"),
+            net_wm_state_maximized_horz: intern_atom("_NET_WM_STATE_MAXIMIZED_HORZ"),
+            net_wm_state_demands_attention: intern_atom("_NET_WM_STATE_DEMANDS_ATTENTION"),
+            net_frame_extents: intern_atom("_NET_FRAME_EXTENTS"),
+            net_wm_window_opacity: intern_atom("_NET_WM_WINDOW_OPACITY"),
+            net_desktop_names: intern_atom("_NET_DESKTOP_NAMES"),
+            net_number_of_desktops: intern_atom("_NET_NUMBER_OF_DESKTOPS"),
+            net_current_desktop: intern_atom("_NET_CURRENT_DESKTOP"),
+            net_wm_desktop: intern_atom("_NET_WM_DESKTOP"),
+            motif_wm_hints: intern_atom("_MOTIF_WM_HINTS"),
+        };
+        #[cfg(feature = "xrandr")]
+        unsafe {
+            wm.init_randr()
+        };
+        unsafe { wm.init_argb_visual() };
+        unsafe { wm.update_monitors() };
+        wm.own_windows.insert(wm.check_window);
+        wm
+    }
+
+    unsafe fn init_argb_visual(&mut self) {
+        let mut visual_info = zeroed();
+        let screen = XDefaultScreen(self.display);
+        if XMatchVisualInfo(self.display, screen, 32, TrueColor, &mut visual_info) == 0 {
+            info!("No 32-bit visual, frames will be opaque");
+            return;
+        }
+
+        let visual = visual_info.visual;
+        let colormap = XCreateColormap(self.display, self.root, visual, AllocNone);
+        // A GC can only draw on drawables of the depth it was created for.
+        let pixmap = XCreatePixmap(self.display, self.root, 1, 1, 32);
+        let gc = XCreateGC(self.display, pixmap, 0, null_mut());
+        XFreePixmap(self.display, pixmap);
+        XSetForeground(self.display, gc, Color(TITLE_COLOR).argb());
+        if !self.font.is_null() {
+            XSetFont(self.display, gc, (*self.font).fid);
+        }
+        self.argb_visual = Some(ArgbVisual {
+            visual,
+            colormap,
+            gc,
+        });
+    }
+
+    unsafe fn compositing(&self) -> bool {
+        XGetSelectionOwner(self.display, self.net_wm_cm) != 0
+    }
+
+    // Frame colors carry an alpha byte on 32-bit frames.
+    fn frame_pixel(&self, w: Window, color: Color) -> c_ulong {
+        if self.clients[&w].argb {
+            color.argb()
+        } else {
+            color.0
+        }
+    }
+
+    unsafe extern "C" fn wm_detected(_display: *mut Display, err: *mut XErrorEvent) -> c_int {
+        if (*err).error_code == BadAccess {
+            WM_DETECTED.store(true, Ordering::SeqCst);
+        }
+        0
+    }
+
+    unsafe extern "C" fn x_error(display: *mut Display, err: *mut XErrorEvent) -> c_int {
+        const MAX_ERROR_TEXT_LENGTH: usize = 1024;
+        let mut error_text = [0u8; MAX_ERROR_TEXT_LENGTH];
+        XGetErrorText(
+            display,
+            (*err).error_code as c_int,
+            error_text.as_mut_ptr() as *mut c_char,
+            MAX_ERROR_TEXT_LENGTH as c_int,
+        );
+        error!(
+            "X error: {}",
+            CStr::from_ptr(error_text.as_ptr() as *const c_char)
+                .to_str()
+                .unwrap_or("`CStr::to_str()` error!")
+        );
+        0
+    }
+
+    // The connection is gone, so no further requests can reach the server. Clients are still
+    // restored: every client is in our save-set, so the server reparents them to root and maps
+    // them when our connection closes.
+    unsafe extern "C" fn xio_error(display: *mut Display) -> c_int {
+        error!(
+            "X IO error: lost connection to \"{}\"",
+            CStr::from_ptr(XDisplayString(display))
+                .to_str()
+                .unwrap_or("`CStr::to_str()` error!")
+        );
+        process::exit(1);
+    }
+
+    // Take over the display: redirect the root window and frame the windows already mapped.
+    pub fn init(&mut self, replace: bool) {
+        unsafe {
+            install_sigchld_handler();
+            self.signal_fd = install_signal_handlers();
+            self.acquire_wm_selection(replace);
+            XSetErrorHandler(Some(WindowManager::wm_detected));
+            XSelectInput(
+                self.display,
+                self.root,
+                SubstructureRedirectMask | SubstructureNotifyMask | PropertyChangeMask,
+            );
+            self.grab_keys();
+            XSync(self.display, 0);
+
+            if WM_DETECTED.load(Ordering::SeqCst) {
+                panic!("Detected another window manager on display!");
+            }
+            self.init_ewmh();
+            self.init_bar();
+
+            XSetErrorHandler(Some(WindowManager::x_error));
+            XSetIOErrorHandler(Some(WindowManager::xio_error));
+            // Button presses only reach the root window over the desktop background, so clients
+            // keep their scroll events, unlike with a grab. Selected separately because only one
+            // client may select them, which must not be mistaken for another window manager.
+            XSelectInput(
+                self.display,
+                self.root,
+                SubstructureRedirectMask
+                    | SubstructureNotifyMask
+                    | PropertyChangeMask
+                    | ButtonPressMask,
+            );
+            self.grab_server();
+
+            let mut returned_root = 0;
+            let mut returned_parent = 0;
+            let mut top_level_windows = null_mut();
+            let mut num_top_level_windows = 0;
+            XQueryTree(
+                self.display,
+                self.root,
+                &mut returned_root,
+                &mut returned_parent,
+                &mut top_level_windows,
+                &mut num_top_level_windows,
+            );
+            assert_eq!(returned_root, self.root);
+
+            for i in 0..num_top_level_windows as usize {
+                self.frame(*top_level_windows.add(i), true);
+            }
+            XFree(top_level_windows as *mut c_void);
+            self.ungrab_server();
+
+            if self.config.ipc {
+                let display = CStr::from_ptr(XDisplayString(self.display)).to_string_lossy();
+                self.ipc = Ipc::bind(&display);
+            }
+        }
+    }
+
+    pub fn autostart(&self) {
+        for cmd in &self.config.autostart {
+            info!("Autostart: {}", cmd);
+            spawn(cmd);
+        }
+    }
+
+    // Handle events until SIGTERM/SIGINT or a quit command, then give all clients back to root.
+    pub fn run(&mut self) {
+        unsafe {
+            let ipc = self.ipc.take();
+            self.event_loop(ipc.as_ref());
+            self.shutdown();
+        }
+    }
+
+    // Drain queued X events, then sleep in `poll()` until the X connection, the signal pipe or
+    // the control socket has something to read.
+    unsafe fn event_loop(&mut self, ipc: Option<&Ipc>) {
+        while !QUIT.load(Ordering::SeqCst) {
+            while XPending(self.display) > 0 && !QUIT.load(Ordering::SeqCst) {
+                let mut e = MaybeUninit::uninit();
+                XNextEvent(self.display, e.as_mut_ptr());
+                self.handle_event(&e.assume_init());
+            }
+            if QUIT.load(Ordering::SeqCst) {
+                break;
+            }
+
+            let mut fds = vec![
+                libc::pollfd {
+                    fd: XConnectionNumber(self.display),
+                    events: libc::POLLIN,
+                    revents: 0,
+                },
+                libc::pollfd {
+                    fd: self.signal_fd,
+                    events: libc::POLLIN,
+                    revents: 0,
+                },
+            ];
+            if let Some(ipc) = ipc {
+                fds.push(libc::pollfd {
+                    fd: ipc.fd(),
+                    events: libc::POLLIN,
+                    revents: 0,
+                });
+            }
+            if libc::poll(fds.as_mut_ptr(), fds.len() as libc::nfds_t, -1) <= 0 {
+                continue;
+            }
+
+            if fds[1].revents & libc::POLLIN != 0 {
+                let mut buf = [0u8; 64];
+                while libc::read(self.signal_fd, buf.as_mut_ptr() as *mut c_void, buf.len()) > 0 {}
+                if RELOAD.swap(false, Ordering::SeqCst) {
+                    self.reload_config();
+                }
+            }
+            if let Some(ipc) = ipc {
+                if fds[2].revents & libc::POLLIN != 0 {
+                    ipc.accept(|command| self.run_command(command));
+                }
+            }
+        }
+    }
+
+    unsafe fn reload_config(&mut self) {
+        let config = match Config::try_load() {
+            Ok(config) => config,
+            Err(e) => {
+                error!("{}, keeping the current config", e);
+                return;
+            }
+        };
+        self.config = config;
+
+        self.grab_keys();
+        let windows: Vec<Window> = self.clients.keys().copied().collect();
+        for w in windows {
+            let client = self.clients.get_mut(&w).unwrap();
+            if client.window_type != WindowType::Splash && !client.borderless {
+                client.border_width = self.config.border_width;
+            }
+            let (frame, border_width) = (client.frame, client.border_width);
+            let fullscreen = client.fullscreen.is_some();
+            let border_color = if self.focused == Some(w) {
+                self.config.focused_border_color
+            } else {
+                self.unfocused_border_color(w)
+            };
+
+            if !fullscreen {
+                XSetWindowBorderWidth(self.display, frame, border_width);
+            }
+            XSetWindowBorder(self.display, frame, self.frame_pixel(w, border_color));
+            XSetWindowBackground(
+                self.display,
+                frame,
+                self.frame_pixel(w, self.config.background_color),
+            );
+            XClearWindow(self.display, frame);
+            self.draw_title(w);
+            self.grab_buttons(w);
+            self.update_frame_extents(w);
+        }
+
+        if self.config.show_bar && self.bar == 0 {
+            self.init_bar();
+        } else if !self.config.show_bar && self.bar != 0 {
+            XDestroyWindow(self.display, self.bar);
+            self.own_windows.remove(&self.bar);
+            self.bar = 0;
+        } else if self.bar != 0 {
+            XSetWindowBackground(self.display, self.bar, self.config.background_color.0);
+            self.draw_bar();
+        }
+
+        self.hide_menu();
+        self.update_desktop_names();
+        self.retile();
+        info!("Reloaded config");
+    }
+
+    unsafe fn handle_event(&mut self, e: &XEvent) {
+        #[allow(non_upper_case_globals)]
+        match e.get_type() {
+            MapRequest => self.map_request(e.as_ref()),
+            UnmapNotify => self.unmap_notify(e.as_ref()),
+            DestroyNotify => self.destroy_notify(e.as_ref()),
+            ConfigureRequest => self.configure_request(e.as_ref()),
+            ConfigureNotify => self.configure_notify(e.as_ref()),
+            ButtonPress => self.button_press(e.as_ref()),
+            ButtonRelease => self.button_release(e.as_ref()),
+            MotionNotify => self.motion_notify(&self.latest_motion(e.as_ref())),
+            KeyPress => self.key_press(e.as_ref()),
+            KeyRelease => self.key_release(e.as_ref()),
+            EnterNotify => self.enter_notify(e.as_ref()),
+            Expose => self.expose(e.as_ref()),
+            PropertyNotify => self.property_notify(e.as_ref()),
+            ClientMessage => self.client_message(e.as_ref()),
+            MappingNotify => self.mapping_notify(e.as_ref()),
+            SelectionClear => self.selection_clear(e.as_ref()),
+            #[cfg(feature = "xrandr")]
+            t if Some(t)
+                == self
+                    .randr_event_base
+                    .map(|base| base + RRScreenChangeNotify) =>
+            {
+                self.screen_change_notify(e)
+            }
+            _ => (),
+        }
+    }
+
+    unsafe fn shutdown(&mut self) {
+        info!("Shutting down");
+        let windows: Vec<Window> = self.clients.keys().copied().collect();
+        for w in windows {
+            if self.minimized.contains(&w) {
+                XMapWindow(self.display, w);
+            }
+            self.unframe(w);
+        }
+        XDestroyWindow(self.display, self.check_window);
+        if self.bar != 0 {
+            XDestroyWindow(self.display, self.bar);
+        }
+        XSetInputFocus(
+            self.display,
+            PointerRoot as Window,
+            RevertToPointerRoot,
+            CurrentTime,
+        );
+        XCloseDisplay(self.display);
+    }
+
+    unsafe fn init_bar(&mut self) {
+        if !self.config.show_bar {
+            return;
+        }
+
+        let mut attrs: XSetWindowAttributes = zeroed();
+        attrs.override_redirect = 1;
+        attrs.background_pixel = self.config.background_color.0;
+        attrs.event_mask = ExposureMask;
+        self.bar = XCreateWindow(
+            self.display,
+            self.root,
+            0,
+            0,
+            1,
+            BAR_HEIGHT as c_uint,
+            0,
+            CopyFromParent,
+            InputOutput as c_uint,
+            null_mut(),
+            CWOverrideRedirect | CWBackPixel | CWEventMask,
+            &mut attrs,
+        );
+        self.own_windows.insert(self.bar);
+        self.status = self.fetch_title(self.root);
+        self.place_bar();
+        XMapRaised(self.display, self.bar);
+    }
+
+    unsafe fn place_bar(&mut self) {
+        if self.bar == 0 {
+            return;
+        }
+
+        let monitor = self.monitors[0].geometry;
+        XMoveResizeWindow(
+            self.display,
+            self.bar,
+            monitor.x,
+            monitor.y,
+            monitor.width as c_uint,
+            BAR_HEIGHT as c_uint,
+        );
+    }
+
+    unsafe fn draw_bar(&self) {
+        XClearWindow(self.display, self.bar);
+        if self.font.is_null() {
+            return;
+        }
+
+        let text = self.status.as_ptr() as *const c_char;
+        let len = self.status.len() as c_int;
+        let (ascent, descent) = ((*self.font).ascent, (*self.font).descent);
+        XDrawString(
+            self.display,
+            self.bar,
+            self.gc,
+            self.monitors[0].geometry.width - XTextWidth(self.font, text, len) - 4,
+            (BAR_HEIGHT - ascent - descent) / 2 + ascent,
+            text,
+            len,
+        );
+    }
+
+    // ICCCM manager selection: take `WM_Sn` and, with `replace`, wait for the previous owner to
+    // destroy its selection window before redirecting the root window.
+    unsafe fn acquire_wm_selection(&self, replace: bool) {
+        let mut owner = XGetSelectionOwner(self.display, self.wm_selection);
+        if owner != 0 {
+            if !replace {
+                panic!(
+                    "Detected another window manager on display, use `--replace` to replace it!"
+                );
+            }
+            // The owner may be gone already, so the `BadWindow` must not reach the default error
+            // handler, which exits.
+            XSetErrorHandler(Some(WindowManager::x_error));
+            XSelectInput(self.display, owner, StructureNotifyMask);
+            XSync(self.display, 0);
+            if XGetSelectionOwner(self.display, self.wm_selection) != owner {
+                owner = 0;
+            }
+        }
+
+        XSetSelectionOwner(
+            self.display,
+            self.wm_selection,
+            self.check_window,
+            CurrentTime,
+        );
+        if XGetSelectionOwner(self.display, self.wm_selection) != self.check_window {
+            panic!("Failed to acquire the window manager selection!");
+        }
+
+        if owner != 0 {
+            info!("Waiting for the running window manager to exit");
+            let mut e = zeroed();
+            let mut released = false;
+            for _ in 0..50 {
+                if XCheckTypedWindowEvent(self.display, owner, DestroyNotify, &mut e) != 0 {
+                    released = true;
+                    break;
+                }
+                thread::sleep(Duration::from_millis(100));
+            }
+            if !released {
+                panic!("The running window manager did not exit!");
+            }
+        }
+
+        let mut data = ClientMessageData::new();
+        data.set_long(0, CurrentTime as c_long);
+        data.set_long(1, self.wm_selection as c_long);
+        data.set_long(2, self.check_window as c_long);
+        let mut msg = XEvent::from(XClientMessageEvent {
+            type_: ClientMessage,
+            serial: 0,
+            send_event: 1,
+            display: self.display,
+            window: self.root,
+            message_type: self.manager,
+            format: 32,
+            data,
+        });
+        XSendEvent(self.display, self.root, 0, StructureNotifyMask, &mut msg);
+    }
+
+    unsafe fn selection_clear(&self, e: &XSelectionClearEvent) {
+        if e.selection == self.wm_selection {
+            info!("Replaced by another window manager");
+            QUIT.store(true, Ordering::SeqCst);
+        }
+    }
+
+    unsafe fn init_ewmh(&mut self) {
+        for &w in &[self.root, self.check_window] {
+            XChangeProperty(
+                self.display,
+                w,
+                self.net_supporting_wm_check,
+                XA_WINDOW,
+                32,
+                PropModeReplace,
+                &self.check_window as *const Window as *const u8,
+                1,
+            );
+        }
+        XChangeProperty(
+            self.display,
+            self.check_window,
+            self.net_wm_name,
+            self.utf8_string,
+            8,
+            PropModeReplace,
+            WM_NAME.as_ptr(),
+            WM_NAME.len() as c_int,
+        );
+
+        let supported = [
+            self.net_supported,
+            self.net_supporting_wm_check,
+            self.net_wm_name,
+            self.net_active_window,
+            self.net_client_list,
+            self.net_wm_window_type,
+            self.net_wm_window_type_dock,
+            self.net_wm_window_type_dialog,
+            self.net_wm_window_type_splash,
+            self.net_wm_strut,
+            self.net_wm_strut_partial,
+            self.net_wm_state,
+            self.net_wm_state_fullscreen,
+            self.net_wm_state_above,
+            self.net_wm_state_below,
+            self.net_wm_state_sticky,
+            self.net_wm_state_maximized_vert,
+            self.net_wm_state_maximized_horz,
+            self.net_wm_state_demands_attention,
+            self.net_frame_extents,
+            self.net_desktop_names,
+            self.net_number_of_desktops,
+            self.net_current_desktop,
+            self.net_wm_desktop,
+        ];
+        XChangeProperty(
+            self.display,
+            self.root,
+            self.net_supported,
+            XA_ATOM,
+            32,
+            PropModeReplace,
+            supported.as_ptr() as *const u8,
+            supported.len() as c_int,
+        );
+        self.update_desktop_names();
+        self.set_cardinal(
+            self.root,
+            self.net_number_of_desktops,
+            WORKSPACE_COUNT as c_ulong,
+        );
+        self.set_cardinal(
+            self.root,
+            self.net_current_desktop,
+            self.current_workspace as c_ulong,
+        );
+    }
+
+    unsafe fn set_cardinal(&self, w: Window, property: Atom, value: c_ulong) {
+        XChangeProperty(
+            self.display,
+            w,
+            property,
+            XA_CARDINAL,
+            32,
+            PropModeReplace,
+            &value as *const c_ulong as *const u8,
+            1,
+        );
+    }
+
+    // Sticky windows are on all desktops, 0xFFFFFFFF.
+    unsafe fn update_wm_desktop(&self, w: Window) {
+        let client = &self.clients[&w];
+        let desktop = if client.sticky {
+            0xffffffff
+        } else {
+            client.workspace as c_ulong
+        };
+        self.set_cardinal(w, self.net_wm_desktop, desktop);
+    }
+
+    // Workspaces without a configured name are named after their number.
+    fn workspace_name(&self, workspace: usize) -> String {
+        match self.config.workspace_names.get(workspace) {
+            Some(name) if !name.is_empty() => name.clone(),
+            _ => (workspace + 1).to_string(),
+        }
+    }
+
+    unsafe fn update_desktop_names(&self) {
+        let mut names = Vec::new();
+        for workspace in 0..WORKSPACE_COUNT {
+            names.extend(self.workspace_name(workspace).bytes());
+            names.push(0);
+        }
+        XChangeProperty(
+            self.display,
+            self.root,
+            self.net_desktop_names,
+            self.utf8_string,
+            8,
+            PropModeReplace,
+            names.as_ptr(),
+            names.len() as c_int,
+        );
+    }
+
+    // The server is grabbed so that the client cannot vanish halfway through framing.
+    unsafe fn frame(&mut self, w: Window, created_before: bool) {
+        self.grab_server();
+        self.frame_grabbed(w, created_before);
+        self.ungrab_server();
+    }
+
+    // Server grabs do not nest, so only the outermost one is passed on.
+    unsafe fn grab_server(&mut self) {
+        if self.server_grabs == 0 {
+            XGrabServer(self.display);
+        }
+        self.server_grabs += 1;
+    }
+
+    unsafe fn ungrab_server(&mut self) {
+        self.server_grabs -= 1;
+        if self.server_grabs == 0 {
+            XUngrabServer(self.display);
+        }
+    }
+
+    unsafe fn frame_grabbed(&mut self, w: Window, created_before: bool) {
+        if self.clients.contains_key(&w) || self.own_windows.contains(&w) {
+            return;
+        }
+
+        let mut x_window_attrs = zeroed();
+        if XGetWindowAttributes(self.display, w, &mut x_window_attrs) == 0 {
+            debug!("Window {} is gone before framing", w);
+            return;
+        }
+        if x_window_attrs.override_redirect != 0
+            || (created_before && x_window_attrs.map_state != IsViewable)
+        {
+            return;
+        }
+
+        let window_type = self.window_type(w);
+        if window_type == WindowType::Dock {
+            let struts = self.read_struts(w);
+            self.docks.insert(w, struts);
+            self.update_struts();
+            info!("Dock window: {} {:?}", w, struts);
+            return;
+        }
+
+        let mut parent = 0;
+        let transient_for = if XGetTransientForHint(self.display, w, &mut parent) != 0
+            && self.clients.contains_key(&parent)
+        {
+            Some(parent)
+        } else {
+            None
+        };
+
+        let class = self.fetch_class(w);
+        let title = self.fetch_title(w);
+        let undecorated = self.motif_undecorated(w);
+        let borderless = self
+            .config
+            .border_rule(class.as_deref(), &title)
+            .map_or(undecorated, |border| !border);
+        let mut floating = window_type == WindowType::Dialog || transient_for.is_some();
+        let mut workspace = transient_for.map_or(self.current_workspace, |parent| {
+            self.clients[&parent].workspace
+        });
+        for rule in &self.config.rules {
+            if !rule.matches(class.as_deref(), &title) {
+                continue;
+            }
+            if let Some(target) = rule.workspace {
+                if (1..=WORKSPACE_COUNT).contains(&target) {
+                    workspace = target - 1;
+                } else {
+                    warn!("Invalid workspace {} in rule", target);
+                }
+            }
+            if let Some(rule_floating) = rule.floating {
+                floating = rule_floating;
+            }
+        }
+        let border_width = if window_type == WindowType::Splash || borderless {
+            0
+        } else {
+            self.config.border_width
+        };
+        let title_height = if window_type == WindowType::Splash || undecorated {
+            0
+        } else {
+            TITLE_HEIGHT
+        };
+
+        let (mut x, mut y) = (x_window_attrs.x, x_window_attrs.y);
+        let outer_size = (
+            x_window_attrs.width + 2 * border_width as c_int,
+            x_window_attrs.height + title_height + 2 * border_width as c_int,
+        );
+        let unplaced = !created_before && x == 0 && y == 0;
+        if let Some(parent) = transient_for {
+            let client = &self.clients[&parent];
+            let geometry = self.geometry(client.frame);
+            let border_width = client.border_width as c_int;
+            x = geometry.x + (geometry.width + 2 * border_width - outer_size.0) / 2;
+            y = geometry.y + (geometry.height + 2 * border_width - outer_size.1) / 2;
+        } else if window_type == WindowType::Dialog
+            || (unplaced && self.layout() != Layout::Floating)
+        {
+            let area = self.usable_area(self.current_monitor());
+            x = area.x + (area.width - outer_size.0) / 2;
+            y = area.y + (area.height - outer_size.1) / 2;
+        } else if unplaced {
+            let position = self.place_window(outer_size.0, outer_size.1);
+            x = position.0;
+            y = position.1;
+        }
+
+        let (mut width, mut height) = (x_window_attrs.width, x_window_attrs.height);
+        if let Some([saved_x, saved_y, saved_width, saved_height]) = class
+            .as_ref()
+            .filter(|_| self.config.remember_geometry && transient_for.is_none())
+            .and_then(|class| self.placements.get(class))
+        {
+            x = saved_x;
+            y = saved_y;
+            width = max(saved_width, 1);
+            height = max(saved_height - title_height, 1);
+            XResizeWindow(self.display, w, width as c_uint, height as c_uint);
+        }
+
+        let argb_visual = self
+            .argb_visual
+            .as_ref()
+            .filter(|_| self.config.argb_frames && self.compositing());
+        let frame: Window = match argb_visual {
+            Some(argb_visual) => {
+                let mut attrs: XSetWindowAttributes = zeroed();
+                attrs.colormap = argb_visual.colormap;
+                attrs.border_pixel = self.config.unfocused_border_color.argb();
+                attrs.background_pixel = self.config.background_color.argb();
+                XCreateWindow(
+                    self.display,
+                    self.root,
+                    x,
+                    y,
+                    width as c_uint,
+                    (height + title_height) as c_uint,
+                    border_width,
+                    32,
+                    InputOutput as c_uint,
+                    argb_visual.visual,
+                    CWColormap | CWBorderPixel | CWBackPixel,
+                    &mut attrs,
+                )
+            }
+            None => XCreateSimpleWindow(
+                self.display,
+                self.root,
+                x,
+                y,
+                width as c_uint,
+                (height + title_height) as c_uint,
+                border_width,
+                self.config.unfocused_border_color.0,
+                self.config.background_color.0,
+            ),
+        };
+        let argb = argb_visual.is_some();
+
+        XSelectInput(
+            self.display,
+            frame,
+            SubstructureRedirectMask
+                | SubstructureNotifyMask
+                | EnterWindowMask
+                | ExposureMask
+                | ButtonPressMask,
+        );
+        XSelectInput(self.display, w, PropertyChangeMask);
+
+        XAddToSaveSet(self.display, w);
+        XSetWindowBorderWidth(self.display, w, 0);
+        XReparentWindow(self.display, w, frame, 0, title_height);
+
+        let mut reparented_attrs = zeroed();
+        if XGetWindowAttributes(self.display, w, &mut reparented_attrs) == 0 {
+            warn!("Window {} is gone while framing", w);
+            XDestroyWindow(self.display, frame);
+            return;
+        }
+        if workspace == self.current_workspace {
+            XMapWindow(self.display, frame);
+        }
+
+        let mut size_hints = zeroed();
+        let mut supplied_hints = 0;
+        XGetWMNormalHints(self.display, w, &mut size_hints, &mut supplied_hints);
+
+        self.clients.insert(
+            w,
+            Client {
+                frame,
+                title,
+                border_width,
+                borderless,
+                title_height,
+                size_hints,
+                workspace,
+                floating,
+                window_type,
+                class,
+                argb,
+                opacity: 1.0,
+                urgent: false,
+                above: false,
+                below: false,
+                sticky: false,
+                geometry: Geometry {
+                    x,
+                    y,
+                    width,
+                    height: height + title_height,
+                },
+                saved_geometry: None,
+                snapped: None,
+                fullscreen: None,
+                transient_for,
+                ignore_unmaps: 0,
+            },
+        );
+        self.frame_to_client.insert(frame, w);
+        self.own_windows.insert(frame);
+        self.set_wm_state(w, NORMAL_STATE);
+        self.update_frame_extents(w);
+        self.update_wm_desktop(w);
+        self.stacking.push(frame);
+        self.focus_stack.push(w);
+        self.client_list.push(w);
+        self.workspaces[workspace].push(w);
+        self.update_client_list();
+        self.restack();
+
+        self.grab_buttons(w);
+        info!(
+            "Framed window: {} [{}] {:?}",
+            w, frame, self.clients[&w].window_type
+        );
+
+        self.update_urgency(w);
+        let states = self.get_property(w, self.net_wm_state, XA_ATOM);
+        for &(atom, state) in &[
+            (self.net_wm_state_above, NetState::Above),
+            (self.net_wm_state_below, NetState::Below),
+            (self.net_wm_state_sticky, NetState::Sticky),
+            (self.net_wm_state_fullscreen, NetState::Fullscreen),
+        ] {
+            if states.contains(&atom) {
+                self.set_net_state(w, state, true);
+            }
+        }
+        self.update_net_wm_state(w);
+    }
+
+    unsafe fn get_property(&self, w: Window, property: Atom, type_: Atom) -> Vec<c_ulong> {
+        let mut actual_type = 0;
+        let mut actual_format = 0;
+        let mut num_items = 0;
+        let mut bytes_after = 0;
+        let mut data = null_mut();
+        let status = XGetWindowProperty(
+            self.display,
+            w,
+            property,
+            0,
+            c_long::MAX,
+            0,
+            type_,
+            &mut actual_type,
+            &mut actual_format,
+            &mut num_items,
+            &mut bytes_after,
+            &mut data,
+        );
+        if status != Success as c_int || data.is_null() {
+            return Vec::new();
+        }
+
+        let values = if actual_type == type_ && actual_format == 32 {
+            slice::from_raw_parts(data as *const c_ulong, num_items as usize).to_vec()
+        } else {
+            Vec::new()
+        };
+        XFree(data as *mut c_void);
+        values
+    }
+
+    unsafe fn read_struts(&self, w: Window) -> Struts {
+        let mut values = self.get_property(w, self.net_wm_strut_partial, XA_CARDINAL);
+        if values.len() < 4 {
+            values = self.get_property(w, self.net_wm_strut, XA_CARDINAL);
+        }
+        if values.len() < 4 {
+            return (0, 0, 0, 0);
+        }
+        (
+            values[0] as c_int,
+            values[1] as c_int,
+            values[2] as c_int,
+            values[3] as c_int,
+        )
+    }
+
+    fn update_struts(&mut self) {
+        self.struts = self.docks.values().fold((0, 0, 0, 0), |acc, struts| {
+            (
+                max(acc.0, struts.0),
+                max(acc.1, struts.1),
+                max(acc.2, struts.2),
+                max(acc.3, struts.3),
+            )
+        });
+    }
+
+    unsafe fn update_monitors(&mut self) {
+        let mut monitors = self.query_randr_monitors();
+        if monitors.is_empty() {
+            monitors = self.query_xinerama_monitors();
+        }
+        if monitors.is_empty() {
+            if cfg!(not(any(feature = "xinerama", feature = "xrandr"))) {
+                info!("Built without the xinerama and xrandr features, using a single monitor");
+            }
+            monitors.push(MonitorInfo {
+                name: "default".to_string(),
+                geometry: self.screen_geometry(),
+            });
+        }
+
+        for monitor in &monitors {
+            info!("Monitor {}: {:?}", monitor.name, monitor.geometry);
+        }
+        self.monitors = monitors;
+    }
+
+    #[cfg(feature = "xrandr")]
+    unsafe fn init_randr(&mut self) {
+        let (mut event_base, mut error_base) = (0, 0);
+        if XRRQueryExtension(self.display, &mut event_base, &mut error_base) != 0 {
+            XRRSelectInput(self.display, self.root, RRScreenChangeNotifyMask);
+            self.randr_event_base = Some(event_base);
+        }
+    }
+
+    #[cfg(feature = "xrandr")]
+    unsafe fn query_randr_monitors(&self) -> Vec<MonitorInfo> {
+        let mut monitors: Vec<MonitorInfo> = Vec::new();
+        if self.randr_event_base.is_none() {
+            return monitors;
+        }
+
+        let resources = XRRGetScreenResourcesCurrent(self.display, self.root);
+        if resources.is_null() {
+            return monitors;
+        }
+        let outputs = slice::from_raw_parts((*resources).outputs, (*resources).noutput as usize);
+        for &output in outputs {
+            let output_info = XRRGetOutputInfo(self.display, resources, output);
+            if output_info.is_null() {
+                continue;
+            }
+            if (*output_info).connection == RR_Connected as Connection && (*output_info).crtc != 0 {
+                let crtc_info = XRRGetCrtcInfo(self.display, resources, (*output_info).crtc);
+                if !crtc_info.is_null() {
+                    let geometry = Geometry {
+                        x: (*crtc_info).x,
+                        y: (*crtc_info).y,
+                        width: (*crtc_info).width as c_int,
+                        height: (*crtc_info).height as c_int,
+                    };
+                    if geometry.width > 0 && !monitors.iter().any(|m| m.geometry == geometry) {
+                        let name = slice::from_raw_parts(
+                            (*output_info).name as *const u8,
+                            (*output_info).nameLen as usize,
+                        );
+                        monitors.push(MonitorInfo {
+                            name: String::from_utf8_lossy(name).into_owned(),
+                            geometry,
+                        });
+                    }
+                    XRRFreeCrtcInfo(crtc_info);
+                }
+            }
+            XRRFreeOutputInfo(output_info);
+        }
+        XRRFreeScreenResources(resources);
+        monitors
+    }
+
+    #[cfg(not(feature = "xrandr"))]
+    unsafe fn query_randr_monitors(&self) -> Vec<MonitorInfo> {
+        Vec::new()
+    }
+
+    #[cfg(feature = "xrandr")]
+    unsafe fn screen_change_notify(&mut self, e: &XEvent) {
+        XRRUpdateConfiguration(&mut { *e });
+        self.update_monitors();
+        self.place_bar();
+        self.retile();
+
+        for (&w, client) in &self.clients {
+            if client.workspace == self.current_workspace
+                && self.layout() != Layout::Floating
+                && !client.floating
+            {
+                continue;
+            }
+
+            let geometry = self.geometry(client.frame);
+            let center = (
+                geometry.x + geometry.width / 2,
+                geometry.y + geometry.height / 2,
+            );
+            if self
+                .monitors
+                .iter()
+                .any(|m| m.geometry.contains(center.0, center.1))
+            {
+                continue;
+            }
+
+            let area = self.usable_area(self.monitors[0].geometry);
+            let x = max(
+                area.x,
+                min(geometry.x, area.x + area.width - geometry.width),
+            );
+            let y = max(
+                area.y,
+                min(geometry.y, area.y + area.height - geometry.height),
+            );
+            XMoveWindow(self.display, client.frame, x, y);
+            debug!("Move window {} back on screen to {}x{}", w, x, y);
+        }
+    }
+
+    #[cfg(feature = "xinerama")]
+    unsafe fn query_xinerama_monitors(&self) -> Vec<MonitorInfo> {
+        let mut monitors: Vec<MonitorInfo> = Vec::new();
+        if XineramaIsActive(self.display) == 0 {
+            return monitors;
+        }
+
+        let mut num_screens = 0;
+        let screens = XineramaQueryScreens(self.display, &mut num_screens);
+        if screens.is_null() {
+            return monitors;
+        }
+        for screen in slice::from_raw_parts(screens, num_screens as usize) {
+            let geometry = Geometry {
+                x: screen.x_org as c_int,
+                y: screen.y_org as c_int,
+                width: screen.width as c_int,
+                height: screen.height as c_int,
+            };
+            if !monitors.iter().any(|m| m.geometry == geometry) {
+                monitors.push(MonitorInfo {
+                    name: format!("xinerama-{}", screen.screen_number),
+                    geometry,
+                });
+            }
+        }
+        XFree(screens as *mut c_void);
+        monitors
+    }
+
+    #[cfg(not(feature = "xinerama"))]
+    unsafe fn query_xinerama_monitors(&self) -> Vec<MonitorInfo> {
+        Vec::new()
+    }
+
+    unsafe fn screen_geometry(&self) -> Geometry {
+        let (width, height) = self.screen_size();
+        Geometry {
+            x: 0,
+            y: 0,
+            width,
+            height,
+        }
+    }
+
+    fn monitor_at(&self, x: c_int, y: c_int) -> Geometry {
+        self.monitors
+            .iter()
+            .map(|m| m.geometry)
+            .find(|m| m.contains(x, y))
+            .unwrap_or(self.monitors[0].geometry)
+    }
+
+    unsafe fn current_monitor(&self) -> Geometry {
+        let (x, y, _) = self.query_pointer();
+        self.monitor_at(x, y)
+    }
+
+    // The pointer position and the modifier and button state.
+    unsafe fn query_pointer(&self) -> (c_int, c_int, c_uint) {
+        let (mut returned_root, mut returned_child) = (0, 0);
+        let (mut root_x, mut root_y, mut win_x, mut win_y) = (0, 0, 0, 0);
+        let mut mask = 0;
+        XQueryPointer(
+            self.display,
+            self.root,
+            &mut returned_root,
+            &mut returned_child,
+            &mut root_x,
+            &mut root_y,
+            &mut win_x,
+            &mut win_y,
+            &mut mask,
+        );
+        (root_x, root_y, mask)
+    }
+
+    unsafe fn monitor_of(&self, w: Window) -> Geometry {
+        let geometry = self.geometry(self.clients[&w].frame);
+        self.monitor_at(
+            geometry.x + geometry.width / 2,
+            geometry.y + geometry.height / 2,
+        )
+    }
+
+    // The monitor minus docks, the status bar and the outer gap.
+    unsafe fn usable_area(&self, monitor: Geometry) -> Geometry {
+        let (screen_width, screen_height) = self.screen_size();
+        let (left, right, mut top, bottom) = self.struts;
+        // The bar only covers the top of the first monitor.
+        if self.bar != 0 && monitor == self.monitors[0].geometry {
+            top = max(top, monitor.y + BAR_HEIGHT);
+        }
+        let gap = self.config.outer_gap;
+        let x = max(monitor.x, left) + gap;
+        let y = max(monitor.y, top) + gap;
+        Geometry {
+            x,
+            y,
+            width: min(monitor.x + monitor.width, screen_width - right) - gap - x,
+            height: min(monitor.y + monitor.height, screen_height - bottom) - gap - y,
+        }
+    }
+
+    unsafe fn window_type(&self, w: Window) -> WindowType {
+        for atom in self.get_property(w, self.net_wm_window_type, XA_ATOM) {
+            if atom == self.net_wm_window_type_dock {
+                return WindowType::Dock;
+            } else if atom == self.net_wm_window_type_dialog {
+                return WindowType::Dialog;
+            } else if atom == self.net_wm_window_type_splash {
+                return WindowType::Splash;
+            }
+        }
+        WindowType::Normal
+    }
+
+    unsafe fn update_client_list(&self) {
+        XChangeProperty(
+            self.display,
+            self.root,
+            self.net_client_list,
+            XA_WINDOW,
+            32,
+            PropModeReplace,
+            self.client_list.as_ptr() as *const u8,
+            self.client_list.len() as c_int,
+        );
+    }
+
+    unsafe fn set_active_window(&self, w: Window) {
+        XChangeProperty(
+            self.display,
+            self.root,
+            self.net_active_window,
+            XA_WINDOW,
+            32,
+            PropModeReplace,
+            &w as *const Window as *const u8,
+            1,
+        );
+    }
+
+    unsafe fn place_window(&self, width: c_int, height: c_int) -> (c_int, c_int) {
+        const GRID_STEP: usize = 32;
+
+        let area = self.usable_area(self.current_monitor());
+        let occupied: Vec<Geometry> = self.workspaces[self.current_workspace]
+            .iter()
+            .filter(|&&w| self.is_visible(w))
+            .map(|w| {
+                let client = &self.clients[w];
+                let geometry = client.geometry;
+                let border_width = client.border_width as c_int;
+                Geometry {
+                    width: geometry.width + 2 * border_width,
+                    height: geometry.height + 2 * border_width,
+                    ..geometry
+                }
+            })
+            .collect();
+        let overlaps = |x: c_int, y: c_int| {
+            occupied.iter().any(|g| {
+                x < g.x + g.width && g.x < x + width && y < g.y + g.height && g.y < y + height
+            })
+        };
+
+        for y in (area.y..=area.y + area.height - height).step_by(GRID_STEP) {
+            for x in (area.x..=area.x + area.width - width).step_by(GRID_STEP) {
+                if !overlaps(x, y) {
+                    return (x, y);
+                }
+            }
+        }
+
+        let offset = (occupied.len() % 10 * GRID_STEP) as c_int;
+        (area.x + offset, area.y + offset)
+    }
+
+    unsafe fn grab_keys(&mut self) {
+        XUngrabKey(self.display, AnyKey, AnyModifier, self.root);
+        self.keybindings.clear();
+        for (modifiers, keysym, action) in self.config.keybindings() {
+            let keycode = XKeysymToKeycode(self.display, keysym) as c_uint;
+            if keycode == 0 {
+                continue;
+            }
+            for &lock in &LOCK_MASKS {
+                XGrabKey(
+                    self.display,
+                    keycode as c_int,
+                    modifiers | lock,
+                    self.root,
+                    0,
+                    GrabModeAsync,
+                    GrabModeAsync,
+                );
+            }
+            self.keybindings.push(KeyBinding {
+                modifiers,
+                keycode,
+                action,
+            });
+        }
+    }
+
+    unsafe fn grab_buttons(&self, w: Window) {
+        let frame = self.clients[&w].frame;
+        let mod_mask = self.config.mod_key.mask();
+        XUngrabButton(self.display, AnyButton as c_uint, AnyModifier, w);
+        XUngrabButton(self.display, AnyButton as c_uint, AnyModifier, frame);
+        for &lock in &LOCK_MASKS {
+            XGrabButton(
+                self.display,
+                AnyButton as c_uint,
+                lock,
+                w,
+                0,
+                ButtonPressMask as c_uint,
+                GrabModeSync,
+                GrabModeAsync,
+                0,
+                0,
+            );
+
+            for &window in &[w, frame] {
+                for &button in &[Button1, Button3] {
+                    XGrabButton(
+                        self.display,
+                        button,
+                        mod_mask | lock,
+                        window,
+                        0,
+                        (ButtonPressMask | ButtonReleaseMask | ButtonMotionMask) as c_uint,
+                        GrabModeAsync,
+                        GrabModeAsync,
+                        0,
+                        0,
+                    );
+                }
+            }
+        }
+    }
+
+    unsafe fn mapping_notify(&mut self, e: &XMappingEvent) {
+        XRefreshKeyboardMapping(&mut { *e });
+        if e.request == MappingKeyboard || e.request == MappingModifier {
+            self.grab_keys();
+        }
+    }
+
+    unsafe fn unframe(&mut self, w: Window) {
+        if !self.clients.contains_key(&w) {
+            return;
+        }
+
+        let client = &self.clients[&w];
+        let frame = client.frame;
+        let (border_width, title_height) = (client.border_width as c_int, client.title_height);
+        XUngrabButton(self.display, AnyButton as c_uint, AnyModifier, w);
+        XSelectInput(self.display, w, NoEventMask);
+        let geometry = self.geometry(frame);
+        XUnmapWindow(self.display, frame);
+        XReparentWindow(
+            self.display,
+            w,
+            self.root,
+            geometry.x + border_width,
+            geometry.y + border_width + title_height,
+        );
+        XRemoveFromSaveSet(self.display, w);
+        XDeleteProperty(self.display, w, self.wm_state);
+        XDeleteProperty(self.display, w, self.net_frame_extents);
+        XDeleteProperty(self.display, w, self.net_wm_desktop);
+        self.forget(w);
+    }
+
+    // Drop the frame and all state of a client whose window is already given back or destroyed.
+    unsafe fn forget(&mut self, w: Window) {
+        let client = self.clients.remove(&w).unwrap();
+        let frame = client.frame;
+        XDestroyWindow(self.display, frame);
+        self.own_windows.remove(&frame);
+        self.frame_to_client.remove(&frame);
+        self.stacking.retain(|&f| f != frame);
+        self.minimized.remove(&w);
+        for client in self.clients.values_mut() {
+            if client.transient_for == Some(w) {
+                client.transient_for = None;
+            }
+        }
+        self.focus_stack.retain(|&c| c != w);
+        self.client_list.retain(|&c| c != w);
+        self.workspaces[client.workspace].retain(|&c| c != w);
+        self.update_client_list();
+        if let Some(index) = self.cycle_index {
+            if index >= self.focus_stack.len() {
+                self.cycle_index = self.focus_stack.len().checked_sub(1);
+            }
+        }
+        if self.focused == Some(w) {
+            self.focused = None;
+            self.set_active_window(0);
+        }
+        if self.drag.window == Some(w) {
+            self.drag = DragInfo::default();
+        }
+        self.placements.save();
+        info!("Unframed window: {}", w);
+    }
+
+    unsafe fn map_request(&mut self, e: &XMapRequestEvent) {
+        if self.own_windows.contains(&e.window) {
+            return;
+        }
+
+        if self.minimized.contains(&e.window) {
+            self.restore(e.window);
+            return;
+        }
+
+        self.frame(e.window, false);
+        XMapWindow(self.display, e.window);
+        self.retile();
+        // Retiling discards the enter events, so a new window is focused directly.
+        if self.clients.contains_key(&e.window) && self.is_visible(e.window) {
+            self.focus(e.window);
+        }
+    }
+
+    unsafe fn unmap_notify(&mut self, e: &XUnmapEvent) {
+        if self.docks.remove(&e.window).is_some() {
+            self.update_struts();
+            info!("Dock window unmapped: {}", e.window);
+            self.retile();
+            return;
+        }
+
+        // Withdrawing a minimized window only sends a synthetic unmap to the root, as the window
+        // itself is unmapped already.
+        if e.event == self.root && (e.send_event == 0 || !self.clients.contains_key(&e.window)) {
+            return;
+        }
+
+        if e.send_event == 0 {
+            if let Some(client) = self.clients.get_mut(&e.window) {
+                if client.ignore_unmaps > 0 {
+                    client.ignore_unmaps -= 1;
+                    return;
+                }
+            }
+        }
+
+        let focused = self.focused == Some(e.window);
+        self.unframe(e.window);
+        self.retile();
+        if focused {
+            self.focus_top();
+        }
+    }
+
+    // Minimized windows are unmapped already, so destroying them produces no `UnmapNotify`.
+    unsafe fn destroy_notify(&mut self, e: &XDestroyWindowEvent) {
+        if self.docks.remove(&e.window).is_some() {
+            self.update_struts();
+            info!("Dock window destroyed: {}", e.window);
+            self.retile();
+            return;
+        }
+        if !self.clients.contains_key(&e.window) {
+            return;
+        }
+
+        let focused = self.focused == Some(e.window);
+        self.forget(e.window);
+        self.retile();
+        if focused {
+            self.focus_top();
+        }
+    }
+
+    fn configure_notify(&mut self, e: &XConfigureEvent) {
+        let w = match self.client_of_frame(e.window) {
+            Some(w) => w,
+            None => return,
+        };
+        let tiled = self.is_tiled(w);
+        let client = self.clients.get_mut(&w).unwrap();
+        client.geometry = Geometry {
+            x: e.x,
+            y: e.y,
+            width: e.width,
+            height: e.height,
+        };
+
+        // Only geometry chosen by the user is remembered, not tiled, maximized or fullscreen.
+        if !self.config.remember_geometry
+            || tiled
+            || client.saved_geometry.is_some()
+            || client.snapped.is_some()
+            || client.fullscreen.is_some()
+        {
+            return;
+        }
+        if let Some(class) = &client.class {
+            self.placements.set(class, [e.x, e.y, e.width, e.height]);
+        }
+    }
+
+    unsafe fn configure_request(&self, e: &XConfigureRequestEvent) {
+        let mut changes = XWindowChanges {
+            x: e.x,
+            y: e.y,
+            width: e.width,
+            height: e.height,
+            border_width: e.border_width,
+            sibling: e.above,
+            stack_mode: e.detail,
+        };
+
+        if let Some(client) = self.clients.get(&e.window) {
+            // Tiled, maximized and fullscreen windows keep the geometry we gave them, and are
+            // only told about it.
+            if self.is_tiled(e.window)
+                || client.fullscreen.is_some()
+                || client.saved_geometry.is_some()
+            {
+                self.send_configure_notify(e.window);
+                return;
+            }
+
+            let (frame, title_height) = (client.frame, client.title_height);
+            let mask = e.value_mask as c_uint;
+            let mut geometry = client.geometry;
+            if mask & CWX as c_uint != 0 {
+                geometry.x = e.x;
+            }
+            if mask & CWY as c_uint != 0 {
+                geometry.y = e.y;
+            }
+            if mask & CWWidth as c_uint != 0 {
+                geometry.width = e.width;
+            }
+            if mask & CWHeight as c_uint != 0 {
+                geometry.height = e.height + title_height;
+            }
+            let geometry = self.clamp_to_screen(geometry);
+
+            let mut frame_changes = XWindowChanges {
+                x: geometry.x,
+                y: geometry.y,
+                width: geometry.width,
+                height: geometry.height,
+                ..changes
+            };
+            // The frame border is ours, not the client's.
+            let frame_mask = mask & !(CWBorderWidth as c_uint);
+            XConfigureWindow(self.display, frame, frame_mask, &mut frame_changes);
+            debug!(
+                "Resize [{}] to {}x{}",
+                frame, geometry.width, geometry.height
+            );
+
+            // The child always fills the frame below the title bar.
+            let mut child_changes = XWindowChanges {
+                x: 0,
+                y: title_height,
+                width: geometry.width,
+                height: geometry.height - title_height,
+                border_width: 0,
+                ..changes
+            };
+            XConfigureWindow(
+                self.display,
+                e.window,
+                (CWX | CWY | CWWidth | CWHeight | CWBorderWidth) as c_uint,
+                &mut child_changes,
+            );
+            debug!(
+                "Resize [{}] to {}x{}",
+                e.window, child_changes.width, child_changes.height
+            );
+            return;
+        }
+        XConfigureWindow(self.display, e.window, e.value_mask as c_uint, &mut changes);
+        debug!(
+            "Resize [{}] to {}x{}",
+            e.window, changes.width, changes.height
+        );
+    }
+
+    // Tell a client its current geometry in root coordinates, as ICCCM asks for refused requests.
+    unsafe fn send_configure_notify(&self, w: Window) {
+        let client = &self.clients[&w];
+        let frame_border_width = if client.fullscreen.is_some() {
+            0
+        } else {
+            client.border_width as c_int
+        };
+        let frame = self.geometry(client.frame);
+        let geometry = self.geometry(w);
+        let mut event = XEvent::from(XConfigureEvent {
+            type_: ConfigureNotify,
+            serial: 0,
+            send_event: 1,
+            display: self.display,
+            event: w,
+            window: w,
+            x: frame.x + frame_border_width + geometry.x,
+            y: frame.y + frame_border_width + geometry.y,
+            width: geometry.width,
+            height: geometry.height,
+            border_width: 0,
+            above: 0,
+            override_redirect: 0,
+        });
+        XSendEvent(self.display, w, 0, StructureNotifyMask, &mut event);
+    }
+
+    // Limit a requested frame geometry to the screen size and keep part of it in the usable area.
+    unsafe fn clamp_to_screen(&self, geometry: Geometry) -> Geometry {
+        let (screen_width, screen_height) = self.screen_size();
+        let width = geometry.width.clamp(1, screen_width);
+        let height = geometry.height.clamp(TITLE_HEIGHT + 1, screen_height);
+        let area =
+            self.usable_area(self.monitor_at(geometry.x + width / 2, geometry.y + height / 2));
+        Geometry {
+            x: geometry.x.clamp(
+                min(area.x, area.x + MIN_VISIBLE - width),
+                max(area.x, area.x + area.width - MIN_VISIBLE),
+            ),
+            // The title bar stays reachable.
+            y: geometry
+                .y
+                .clamp(area.y, max(area.y, area.y + area.height - MIN_VISIBLE)),
+            width,
+            height,
+        }
+    }
+
+    unsafe fn button_press(&mut self, e: &XButtonEvent) {
+        if self.menu != 0 && e.window == self.menu {
+            if self.menu_entry_at(e.x, e.y).is_none() {
+                self.hide_menu();
+            }
+            return;
+        }
+        if e.window == self.root {
+            self.root_button_press(e);
+            return;
+        }
+
+        let w = match self.client_of(e.window) {
+            Some(w) => w,
+            None => return,
+        };
+
+        let frame = self.clients[&w].frame;
+        let mod_mask = self.config.mod_key.mask();
+        if e.window == frame
+            && e.button == Button1
+            && e.state & mod_mask == 0
+            && self.close_button(w).contains(e.x, e.y)
+        {
+            self.close(w);
+            return;
+        }
+
+        self.drag.window = Some(w);
+        self.drag.button = if e.state & mod_mask != 0 && self.clients[&w].fullscreen.is_none() {
+            e.button
+        } else {
+            0
+        };
+        self.drag.moved = false;
+        self.drag.start_pos = (e.x_root, e.y_root);
+
+        let geometry = self.geometry(frame);
+        self.drag.start_frame_pos = (geometry.x, geometry.y);
+        self.drag.start_frame_size = (geometry.width, geometry.height);
+
+        self.focus(w);
+
+        if e.state & mod_mask == 0 {
+            XAllowEvents(self.display, ReplayPointer, CurrentTime);
+        }
+    }
+
+    // Scrolling over the desktop switches to the previous or next workspace.
+    unsafe fn root_button_press(&mut self, e: &XButtonEvent) {
+        let count = self.workspaces.len();
+        #[allow(non_upper_case_globals)]
+        match e.button {
+            Button4 => self.switch_workspace((self.current_workspace + count - 1) % count),
+            Button5 => self.switch_workspace((self.current_workspace + 1) % count),
+            Button3 => self.show_menu(e.x_root, e.y_root),
+            _ => (),
+        }
+    }
+
+    unsafe fn show_menu(&mut self, x: c_int, y: c_int) {
+        if self.menu != 0 || self.config.menu.is_empty() {
+            return;
+        }
+
+        let text_width = self
+            .config
+            .menu
+            .iter()
+            .map(|entry| {
+                if self.font.is_null() {
+                    0
+                } else {
+                    let label = &entry.label;
+                    XTextWidth(
+                        self.font,
+                        label.as_ptr() as *const c_char,
+                        label.len() as c_int,
+                    )
+                }
+            })
+            .max()
+            .unwrap_or(0);
+        let width = max(text_width + 2 * OVERLAY_PADDING, 120);
+        let height = self.config.menu.len() as c_int * TITLE_HEIGHT;
+        // Just below and right of the pointer, unless that does not fit on the monitor.
+        let monitor = self.monitor_at(x, y);
+        let x = min(x + 1, monitor.x + monitor.width - width);
+        let y = min(y + 1, monitor.y + monitor.height - height);
+
+        let mut attrs: XSetWindowAttributes = zeroed();
+        attrs.override_redirect = 1;
+        attrs.background_pixel = self.config.background_color.0;
+        attrs.border_pixel = self.config.unfocused_border_color.0;
+        attrs.event_mask = ExposureMask;
+        self.menu = XCreateWindow(
+            self.display,
+            self.root,
+            x,
+            y,
+            width as c_uint,
+            height as c_uint,
+            1,
+            CopyFromParent,
+            InputOutput as c_uint,
+            null_mut(),
+            CWOverrideRedirect | CWBackPixel | CWBorderPixel | CWEventMask,
+            &mut attrs,
+        );
+        self.own_windows.insert(self.menu);
+        self.menu_selected = None;
+        self.menu_opening = true;
+        XMapRaised(self.display, self.menu);
+        XGrabPointer(
+            self.display,
+            self.menu,
+            0,
+            (ButtonPressMask | ButtonReleaseMask | PointerMotionMask) as c_uint,
+            GrabModeAsync,
+            GrabModeAsync,
+            0,
+            0,
+            CurrentTime,
+        );
+    }
+
+    unsafe fn hide_menu(&mut self) {
+        if self.menu == 0 {
+            return;
+        }
+        XUngrabPointer(self.display, CurrentTime);
+        XDestroyWindow(self.display, self.menu);
+        self.own_windows.remove(&self.menu);
+        self.menu = 0;
+    }
+
+    unsafe fn menu_entry_at(&self, x: c_int, y: c_int) -> Option<usize> {
+        let geometry = self.geometry(self.menu);
+        if x < 0 || x >= geometry.width || y < 0 || y >= geometry.height {
+            return None;
+        }
+        Some(((y / TITLE_HEIGHT) as usize).min(self.config.menu.len() - 1))
+    }
+
+    unsafe fn draw_menu(&self) {
+        XClearWindow(self.display, self.menu);
+        let width = self.geometry(self.menu).width;
+        for (i, entry) in self.config.menu.iter().enumerate() {
+            let y = i as c_int * TITLE_HEIGHT;
+            if self.menu_selected == Some(i) {
+                XSetForeground(self.display, self.gc, self.config.focused_border_color.0);
+                XFillRectangle(
+                    self.display,
+                    self.menu,
+                    self.gc,
+                    0,
+                    y,
+                    width as c_uint,
+                    TITLE_HEIGHT as c_uint,
+                );
+                XSetForeground(self.display, self.gc, TITLE_COLOR);
+            }
+            if self.font.is_null() {
+                continue;
+            }
+
+            let (ascent, descent) = ((*self.font).ascent, (*self.font).descent);
+            XDrawString(
+                self.display,
+                self.menu,
+                self.gc,
+                OVERLAY_PADDING,
+                y + (TITLE_HEIGHT - ascent - descent) / 2 + ascent,
+                entry.label.as_ptr() as *const c_char,
+                entry.label.len() as c_int,
+            );
+        }
+    }
+
+    unsafe fn menu_motion(&mut self, e: &XMotionEvent) {
+        let selected = self.menu_entry_at(e.x, e.y);
+        if selected != self.menu_selected {
+            self.menu_selected = selected;
+            self.draw_menu();
+        }
+    }
+
+    // Releasing over an entry runs it, releasing elsewhere closes the menu unless it is the
+    // release of the press that opened it.
+    unsafe fn menu_button_release(&mut self, e: &XButtonEvent) {
+        // The release of the click that opened the menu may land on an entry when the menu is
+        // moved to fit on the monitor, so it never selects one.
+        if take(&mut self.menu_opening) {
+            return;
+        }
+        let entry = match self.menu_entry_at(e.x, e.y) {
+            Some(entry) => entry,
+            None => {
+                self.hide_menu();
+                return;
+            }
+        };
+
+        self.hide_menu();
+        let command = self.config.menu[entry].action.clone();
+        match Action::parse(&command) {
+            Some(action) => self.run_action(action),
+            None => warn!("Invalid menu action \"{}\"", command),
+        }
+    }
+
+    unsafe fn button_release(&mut self, e: &XButtonEvent) {
+        if self.menu != 0 && e.window == self.menu {
+            self.menu_button_release(e);
+            return;
+        }
+
+        let drag = take(&mut self.drag);
+        let w = match self.client_of(e.window) {
+            Some(w) => w,
+            None => return,
+        };
+        if drag.window != Some(w) || drag.button != e.button || !drag.moved {
+            return;
+        }
+
+        let geometry = self.geometry(self.clients[&w].frame);
+        debug!("Drag [{}] ended at {:?}", w, geometry);
+        self.placements.save();
+        if drag.button != Button1 {
+            return;
+        }
+
+        let monitor = self.monitor_at(e.x_root, e.y_root);
+        let area = self.usable_area(monitor);
+        let half_width = area.width / 2;
+        let target = if e.x_root <= monitor.x {
+            Some((area.x, half_width))
+        } else if e.x_root >= monitor.x + monitor.width - 1 {
+            Some((area.x + half_width, area.width - half_width))
+        } else {
+            None
+        };
+
+        if let Some((x, width)) = target {
+            if self.clients[&w].snapped.is_none() {
+                let saved = self.geometry(self.clients[&w].frame);
+                self.clients.get_mut(&w).unwrap().snapped = Some(saved);
+            }
+            self.move_resize(w, x, area.y, width, area.height);
+        } else if e.y_root <= monitor.y && self.clients[&w].saved_geometry.is_none() {
+            self.toggle_maximize(w);
+        } else if self.config.grid_size > 1 && e.state & ShiftMask == 0 {
+            let grid_size = self.config.grid_size;
+            XMoveWindow(
+                self.display,
+                self.clients[&w].frame,
+                snap_to_grid(geometry.x, grid_size),
+                snap_to_grid(geometry.y, grid_size),
+            );
+        }
+    }
+
+    unsafe fn geometry(&self, w: Window) -> Geometry {
+        let mut returned_root = 0;
+        let (mut x, mut y) = (0, 0);
+        let (mut width, mut height, mut border_width, mut depth) = (0, 0, 0, 0);
+        XGetGeometry(
+            self.display,
+            w,
+            &mut returned_root,
+            &mut x,
+            &mut y,
+            &mut width,
+            &mut height,
+            &mut border_width,
+            &mut depth,
+        );
+        Geometry {
+            x,
+            y,
+            width: width as c_int,
+            height: height as c_int,
+        }
+    }
+
+    unsafe fn screen_size(&self) -> (c_int, c_int) {
+        let mut root_attrs = zeroed();
+        XGetWindowAttributes(self.display, self.root, &mut root_attrs);
+        (root_attrs.width, root_attrs.height)
+    }
+
+    unsafe fn enter_notify(&mut self, e: &XCrossingEvent) {
+        if !FOCUS_FOLLOWS_MOUSE || e.mode != NotifyNormal {
+            return;
+        }
+
+        // Also keeps a warp from refocusing the window it was warped to.
+        if let Some(w) = self
+            .client_of_frame(e.window)
+            .filter(|&w| self.focused != Some(w))
+        {
+            self.focus(w);
+        }
+    }
+
+    fn client_of_frame(&self, frame: Window) -> Option<Window> {
+        self.frame_to_client.get(&frame).copied()
+    }
+
+    fn client_of(&self, w: Window) -> Option<Window> {
+        if self.clients.contains_key(&w) {
+            Some(w)
+        } else {
+            self.client_of_frame(w)
+        }
+    }
+
+    unsafe fn expose(&self, e: &XExposeEvent) {
+        if e.count != 0 {
+            return;
+        }
+
+        if e.window == self.bar {
+            self.draw_bar();
+            return;
+        }
+        if e.window == self.cycle_overlay {
+            self.draw_cycle_overlay();
+            return;
+        }
+        if e.window == self.menu {
+            self.draw_menu();
+            return;
+        }
+
+        if let Some(w) = self.client_of_frame(e.window) {
+            self.draw_title(w);
+        }
+    }
+
+    unsafe fn property_notify(&mut self, e: &XPropertyEvent) {
+        if e.atom == self.motif_wm_hints && self.clients.contains_key(&e.window) {
+            let client = &self.clients[&e.window];
+            let undecorated = self.motif_undecorated(e.window);
+            if client.window_type != WindowType::Splash && (client.title_height == 0) != undecorated
+            {
+                self.set_undecorated(e.window, undecorated);
+            }
+            return;
+        }
+        if e.atom == XA_WM_HINTS && self.clients.contains_key(&e.window) {
+            self.update_urgency(e.window);
+            return;
+        }
+        if e.atom != XA_WM_NAME && e.atom != self.net_wm_name {
+            return;
+        }
+
+        if e.window == self.root && self.bar != 0 {
+            self.status = self.fetch_title(self.root);
+            self.draw_bar();
+            return;
+        }
+
+        if self.clients.contains_key(&e.window) {
+            let title = self.fetch_title(e.window);
+            debug!("Title of {} changed: \"{}\"", e.window, title);
+            self.clients.get_mut(&e.window).unwrap().title = title;
+            self.draw_title(e.window);
+        }
+    }
+
+    unsafe fn client_message(&mut self, e: &XClientMessageEvent) {
+        if e.message_type == self.net_current_desktop {
+            self.switch_workspace(e.data.get_long(0) as usize);
+        } else if e.message_type == self.net_active_window {
+            let workspace = match self.clients.get(&e.window) {
+                Some(client) if client.sticky => self.current_workspace,
+                Some(client) => client.workspace,
+                None => return,
+            };
+            self.switch_workspace(workspace);
+            self.restore(e.window);
+            self.focus(e.window);
+        } else if e.message_type == self.net_wm_state {
+            if !self.clients.contains_key(&e.window) {
+                return;
+            }
+            let mut states = Vec::new();
+            for &atom in &[e.data.get_long(1) as Atom, e.data.get_long(2) as Atom] {
+                let state = if atom == self.net_wm_state_fullscreen {
+                    NetState::Fullscreen
+                } else if atom == self.net_wm_state_above {
+                    NetState::Above
+                } else if atom == self.net_wm_state_below {
+                    NetState::Below
+                } else if atom == self.net_wm_state_sticky {
+                    NetState::Sticky
+                } else if atom == self.net_wm_state_maximized_vert
+                    || atom == self.net_wm_state_maximized_horz
+                {
+                    NetState::Maximized
+                } else {
+                    continue;
+                };
+                // Both maximized atoms usually come together and must toggle only once.
+                if !states.contains(&state) {
+                    states.push(state);
+                }
+            }
+
+            for state in states {
+                // _NET_WM_STATE_REMOVE = 0, _NET_WM_STATE_ADD = 1, _NET_WM_STATE_TOGGLE = 2
+                let value = match e.data.get_long(0) {
+                    0 => false,
+                    1 => true,
+                    2 => !self.net_state(e.window, state),
+                    _ => continue,
+                };
+                self.set_net_state(e.window, state, value);
+            }
+        } else if e.message_type == self.net_wm_desktop {
+            if !self.clients.contains_key(&e.window) {
+                return;
+            }
+            let desktop = e.data.get_long(0) as c_ulong & 0xffffffff;
+            if desktop == 0xffffffff {
+                self.set_sticky(e.window, true);
+            } else {
+                self.set_sticky(e.window, false);
+                self.move_to_workspace(e.window, desktop as usize);
+            }
+        } else if e.message_type == self.wm_change_state
+            && e.data.get_long(0) == ICONIC_STATE as c_long
+        {
+            self.minimize(e.window);
+        }
+    }
+
+    unsafe fn fetch_class(&self, w: Window) -> Option<String> {
+        let mut class_hint = zeroed::<XClassHint>();
+        if XGetClassHint(self.display, w, &mut class_hint) == 0 {
+            return None;
+        }
+        let class = if class_hint.res_class.is_null() {
+            None
+        } else {
+            Some(
+                CStr::from_ptr(class_hint.res_class)
+                    .to_string_lossy()
+                    .into_owned(),
+            )
+        };
+        for name in &[class_hint.res_name, class_hint.res_class] {
+            if !name.is_null() {
+                XFree(*name as *mut c_void);
+            }
+        }
+        class
+    }
+
+    unsafe fn fetch_title(&self, w: Window) -> String {
+        let mut text_prop = zeroed::<XTextProperty>();
+        if XGetTextProperty(self.display, w, &mut text_prop, self.net_wm_name) != 0
+            && !text_prop.value.is_null()
+        {
+            let value = slice::from_raw_parts(text_prop.value, text_prop.nitems as usize);
+            let title = String::from_utf8_lossy(value).into_owned();
+            XFree(text_prop.value as *mut c_void);
+            return title;
+        }
+
+        let mut name = null_mut();
+        if XFetchName(self.display, w, &mut name) == 0 || name.is_null() {
+            return String::new();
+        }
+        let title = CStr::from_ptr(name).to_string_lossy().into_owned();
+        XFree(name as *mut c_void);
+        title
+    }
+
+    // A square at the right end of the title bar, relative to the frame.
+    unsafe fn close_button(&self, w: Window) -> Geometry {
+        let client = &self.clients[&w];
+        let width = self.geometry(client.frame).width;
+        Geometry {
+            x: width - client.title_height,
+            y: 0,
+            width: client.title_height,
+            height: client.title_height,
+        }
+    }
+
+    unsafe fn draw_title(&self, w: Window) {
+        let client = &self.clients[&w];
+        if client.title_height == 0 {
+            return;
+        }
+        let gc = match &self.argb_visual {
+            Some(argb_visual) if client.argb => argb_visual.gc,
+            _ => self.gc,
+        };
+        XClearArea(
+            self.display,
+            client.frame,
+            0,
+            0,
+            0,
+            client.title_height as c_uint,
+            0,
+        );
+
+        const INSET: c_int = 5;
+        let button = self.close_button(w);
+        let (left, right) = (button.x + INSET, button.x + button.width - 1 - INSET);
+        let (top, bottom) = (button.y + INSET, button.y + button.height - 1 - INSET);
+        XDrawLine(self.display, client.frame, gc, left, top, right, bottom);
+        XDrawLine(self.display, client.frame, gc, left, bottom, right, top);
+
+        if self.font.is_null() {
+            return;
+        }
+
+        let (ascent, descent) = ((*self.font).ascent, (*self.font).descent);
+        XDrawString(
+            self.display,
+            client.frame,
+            gc,
+            4,
+            (client.title_height - ascent - descent) / 2 + ascent,
+            client.title.as_ptr() as *const c_char,
+            client.title.len() as c_int,
+        );
+    }
+
+    fn unfocused_border_color(&self, w: Window) -> Color {
+        if self.clients[&w].urgent {
+            self.config.urgent_border_color
+        } else {
+            self.config.unfocused_border_color
+        }
+    }
+
+    unsafe fn update_urgency(&mut self, w: Window) {
+        let hints = XGetWMHints(self.display, w);
+        if hints.is_null() {
+            return;
+        }
+        let urgent = (*hints).flags & XUrgencyHint != 0;
+        XFree(hints as *mut c_void);
+
+        if self.focused == Some(w) {
+            if urgent {
+                self.clear_urgency(w);
+            }
+            return;
+        }
+        let client = self.clients.get_mut(&w).unwrap();
+        if client.urgent == urgent {
+            return;
+        }
+        client.urgent = urgent;
+        let frame = client.frame;
+        XSetWindowBorder(
+            self.display,
+            frame,
+            self.frame_pixel(w, self.unfocused_border_color(w)),
+        );
+        self.update_net_wm_state(w);
+        if urgent {
+            info!("Window {} is urgent", w);
+        }
+    }
+
+    // Focusing a window clears the urgency hint, as the client has got the attention it wanted.
+    unsafe fn clear_urgency(&mut self, w: Window) {
+        self.clients.get_mut(&w).unwrap().urgent = false;
+        let hints = XGetWMHints(self.display, w);
+        if !hints.is_null() {
+            if (*hints).flags & XUrgencyHint != 0 {
+                (*hints).flags &= !XUrgencyHint;
+                XSetWMHints(self.display, w, hints);
+            }
+            XFree(hints as *mut c_void);
+        }
+        self.update_net_wm_state(w);
+    }
+
+    unsafe fn focus(&mut self, w: Window) {
+        if !self.clients.contains_key(&w) {
+            return;
+        }
+
+        if let Some(prev) = self.focused {
+            if let Some(prev_client) = self.clients.get(&prev) {
+                XSetWindowBorder(
+                    self.display,
+                    prev_client.frame,
+                    self.frame_pixel(prev, self.unfocused_border_color(prev)),
+                );
+            }
+        }
+        if self.clients[&w].urgent {
+            self.clear_urgency(w);
+        }
+
+        // The frame must be viewable before it can take the focus.
+        let monocle = self.layout() == Layout::Monocle && self.is_tiled(w) && self.is_visible(w);
+        if monocle {
+            XMapWindow(self.display, self.clients[&w].frame);
+        }
+
+        XSetWindowBorder(
+            self.display,
+            self.clients[&w].frame,
+            self.frame_pixel(w, self.config.focused_border_color),
+        );
+        if self.accepts_input(w) {
+            XSetInputFocus(self.display, w, RevertToPointerRoot, CurrentTime);
+        }
+        if self.supports_protocol(w, self.wm_take_focus) {
+            self.send_protocol(w, self.wm_take_focus);
+        }
+        self.focused = Some(w);
+        self.set_active_window(w);
+        self.raise(w);
+
+        if self.cycle_index.is_none() {
+            self.focus_stack.retain(|&c| c != w);
+            self.focus_stack.insert(0, w);
+        }
+        if monocle {
+            self.retile();
+        }
+    }
+
+    // Transients stay above the window they belong to.
+    unsafe fn raise(&mut self, w: Window) {
+        let mut frames = vec![self.clients[&w].frame];
+        frames.extend(self.stacking.iter().copied().filter(|&f| {
+            self.client_of_frame(f)
+                .is_some_and(|c| self.clients[&c].transient_for == Some(w))
+        }));
+        self.stacking.retain(|f| !frames.contains(f));
+        self.stacking.extend(frames);
+        self.restack();
+    }
+
+    unsafe fn lower(&mut self, w: Window) {
+        let frame = self.clients[&w].frame;
+        self.stacking.retain(|&f| f != frame);
+        self.stacking.insert(0, frame);
+        XLowerWindow(self.display, frame);
+
+        // With focus following the mouse, focus moves with the EnterNotify instead.
+        if FOCUS_FOLLOWS_MOUSE {
+            return;
+        }
+        if let Some(top) = self
+            .stacking
+            .iter()
+            .rev()
+            .filter_map(|&f| self.client_of_frame(f))
+            .find(|&w| self.is_visible(w))
+        {
+            self.focus(top);
+        }
+    }
+
+    // Fullscreen windows stay on top, then the bar and keep-above windows, keep-below ones stay at
+    // the bottom and, in tiling mode, floating windows stay above tiled ones.
+    unsafe fn restack(&self) {
+        let rank = |frame: &Window| match self.client_of_frame(*frame) {
+            Some(w) if self.clients[&w].fullscreen.is_some() => 0,
+            _ if *frame == self.bar => 1,
+            Some(w) if self.clients[&w].above => 2,
+            Some(w) if self.clients[&w].below => 5,
+            Some(w) if self.clients[&w].floating && self.layout() != Layout::Floating => 3,
+            _ => 4,
+        };
+        let mut windows: Vec<Window> = self.stacking.iter().rev().copied().collect();
+        if self.bar != 0 {
+            windows.push(self.bar);
+        }
+        windows.sort_by_key(rank);
+        XRestackWindows(self.display, windows.as_mut_ptr(), windows.len() as c_int);
+        self.discard_enter_events();
+    }
+
+    // Windows moved or restacked under the pointer must not take focus from the mouse.
+    unsafe fn discard_enter_events(&self) {
+        if !FOCUS_FOLLOWS_MOUSE {
+            return;
+        }
+        XSync(self.display, False);
+        let mut e = zeroed();
+        while XCheckMaskEvent(self.display, EnterWindowMask, &mut e) != 0 {}
+    }
+
+    unsafe fn set_above(&mut self, w: Window, above: bool) {
+        match self.clients.get_mut(&w) {
+            Some(client) if client.above != above => {
+                client.above = above;
+                client.below &= !above;
+            }
+            _ => return,
+        }
+        self.update_net_wm_state(w);
+        self.restack();
+        info!("Set keep above of window {} to {}", w, above);
+    }
+
+    unsafe fn set_below(&mut self, w: Window, below: bool) {
+        match self.clients.get_mut(&w) {
+            Some(client) if client.below != below => {
+                client.below = below;
+                client.above &= !below;
+            }
+            _ => return,
+        }
+        self.update_net_wm_state(w);
+        self.restack();
+        info!("Set keep below of window {} to {}", w, below);
+    }
+
+    // Sticky windows stay in their own workspace but are shown on all of them, floating.
+    unsafe fn set_sticky(&mut self, w: Window, sticky: bool) {
+        match self.clients.get_mut(&w) {
+            Some(client) if client.sticky != sticky => client.sticky = sticky,
+            _ => return,
+        }
+        let client = &self.clients[&w];
+        if client.workspace != self.current_workspace && !self.minimized.contains(&w) {
+            if sticky {
+                XMapWindow(self.display, client.frame);
+            } else {
+                XUnmapWindow(self.display, client.frame);
+            }
+        }
+        self.update_net_wm_state(w);
+        self.update_wm_desktop(w);
+        self.retile();
+        info!("Set sticky of window {} to {}", w, sticky);
+    }
+
+    fn net_state(&self, w: Window, state: NetState) -> bool {
+        let client = &self.clients[&w];
+        match state {
+            NetState::Fullscreen => client.fullscreen.is_some(),
+            NetState::Above => client.above,
+            NetState::Below => client.below,
+            NetState::Sticky => client.sticky,
+            NetState::Maximized => client.saved_geometry.is_some(),
+        }
+    }
+
+    unsafe fn set_net_state(&mut self, w: Window, state: NetState, value: bool) {
+        match state {
+            NetState::Fullscreen => self.set_fullscreen(w, value),
+            NetState::Above => self.set_above(w, value),
+            NetState::Below => self.set_below(w, value),
+            NetState::Sticky => self.set_sticky(w, value),
+            NetState::Maximized => {
+                if value != self.net_state(w, state) {
+                    self.toggle_maximize(w);
+                }
+            }
+        }
+    }
+
+    unsafe fn key_press(&mut self, e: &XKeyEvent) {
+        const MODIFIER_MASK: c_uint = ShiftMask | ControlMask | Mod1Mask | Mod4Mask;
+
+        let action = match self.keybindings.iter().find(|binding| {
+            binding.keycode == e.keycode && binding.modifiers == e.state & MODIFIER_MASK
+        }) {
+            Some(binding) => binding.action.clone(),
+            None => return,
+        };
+        self.run_action(action);
+    }
+
+    unsafe fn run_action(&mut self, action: Action) {
+        match action {
+            Action::Close => {
+                if let Some(w) = self.focused {
+                    self.close(w);
+                }
+            }
+            Action::Kill => {
+                if let Some(w) = self.focused {
+                    self.kill(w);
+                }
+            }
+            Action::Maximize => {
+                if let Some(w) = self.focused {
+                    self.toggle_maximize(w);
+                }
+            }
+            Action::Lower => {
+                if let Some(w) = self.focused {
+                    self.lower(w);
+                }
+            }
+            Action::Minimize => {
+                if let Some(w) = self.focused {
+                    self.minimize(w);
+                }
+            }
+            Action::NextWindow => self.cycle_focus(),
+            Action::ToggleLayout => self.toggle_layout(),
+            Action::CycleLayout => self.cycle_layout(),
+            Action::AdjustMasterRatio(delta) => self.adjust_master_ratio(delta),
+            Action::AdjustOpacity(delta) => {
+                if let Some(w) = self.focused {
+                    self.adjust_opacity(w, delta);
+                }
+            }
+            Action::SwapMaster => {
+                if let Some(w) = self.focused {
+                    self.swap_master(w);
+                }
+            }
+            Action::ToggleFloating => {
+                if let Some(w) = self.focused {
+                    self.toggle_floating(w);
+                }
+            }
+            Action::ToggleBorder => {
+                if let Some(w) = self.focused {
+                    let borderless = self.clients[&w].borderless;
+                    self.set_borderless(w, !borderless);
+                }
+            }
+            Action::ToggleAbove => {
+                if let Some(w) = self.focused {
+                    let above = self.clients[&w].above;
+                    self.set_above(w, !above);
+                }
+            }
+            Action::ToggleSticky => {
+                if let Some(w) = self.focused {
+                    let sticky = self.clients[&w].sticky;
+                    self.set_sticky(w, !sticky);
+                }
+            }
+            Action::Workspace(target) => self.switch_workspace(target),
+            Action::MoveToWorkspace(target) => {
+                if let Some(w) = self.focused {
+                    self.move_to_workspace(w, target);
+                }
+            }
+            Action::SendToMonitor(offset) => {
+                if let Some(w) = self.focused {
+                    self.send_to_monitor(w, offset);
+                }
+            }
+            Action::Move(dx, dy) => {
+                if let Some(w) = self.focused {
+                    self.keyboard_move(w, dx, dy);
+                }
+            }
+            Action::Resize(dx, dy) => {
+                if let Some(w) = self.focused {
+                    self.keyboard_resize(w, dx, dy);
+                }
+            }
+            Action::Spawn(cmd) => spawn(&cmd),
+            Action::Reload => self.reload_config(),
+            Action::DumpState => self.dump_state(),
+            Action::Quit => QUIT.store(true, Ordering::SeqCst),
+        }
+    }
+
+    // Tiling follows the pointer, so it is warped along and tiled windows land on the target monitor.
+    unsafe fn send_to_monitor(&mut self, w: Window, offset: isize) {
+        let count = self.monitors.len() as isize;
+        if count < 2 {
+            return;
+        }
+        let source = self.monitor_of(w);
+        let index = self
+            .monitors
+            .iter()
+            .position(|m| m.geometry == source)
+            .unwrap_or(0) as isize;
+        let target = &self.monitors[(index + offset).rem_euclid(count) as usize];
+        let (target, name) = (target.geometry, target.name.clone());
+
+        let fullscreen = self.clients[&w].fullscreen.is_some();
+        if fullscreen {
+            self.set_fullscreen(w, false);
+        }
+
+        let (from, to) = (self.usable_area(source), self.usable_area(target));
+        let translate = |geometry: Geometry| {
+            let width = min(geometry.width, to.width);
+            let height = min(geometry.height, to.height);
+            Geometry {
+                x: to.x + (geometry.x - from.x) * (to.width - width) / max(from.width - width, 1),
+                y: to.y
+                    + (geometry.y - from.y) * (to.height - height) / max(from.height - height, 1),
+                width,
+                height,
+            }
+        };
+        let frame = self.clients[&w].frame;
+        let geometry = self.geometry(frame);
+        let client = self.clients.get_mut(&w).unwrap();
+        client.snapped = client.snapped.map(translate);
+        if let Some(saved) = client.saved_geometry {
+            client.saved_geometry = Some(translate(saved));
+            self.move_resize(w, to.x, to.y, to.width, to.height);
+        } else {
+            self.set_frame_geometry(w, translate(geometry));
+        }
+
+        XWarpPointer(
+            self.display,
+            0,
+            self.root,
+            0,
+            0,
+            0,
+            0,
+            target.x + target.width / 2,
+            target.y + target.height / 2,
+        );
+        if fullscreen {
+            self.set_fullscreen(w, true);
+        }
+        self.retile();
+        self.raise(w);
+        info!("Send window {} to monitor {}", w, name);
+    }
+
+    unsafe fn keyboard_move(&self, w: Window, dx: c_int, dy: c_int) {
+        let frame = self.clients[&w].frame;
+        let border_width = self.clients[&w].border_width as c_int;
+        let geometry = self.geometry(frame);
+        let area = self.usable_area(self.monitor_of(w));
+        let step = self.config.keyboard_step;
+
+        let x = min(
+            geometry.x + dx * step,
+            area.x + area.width - geometry.width - 2 * border_width,
+        );
+        let y = min(
+            geometry.y + dy * step,
+            area.y + area.height - geometry.height - 2 * border_width,
+        );
+        XMoveWindow(self.display, frame, max(x, area.x), max(y, area.y));
+        self.discard_enter_events();
+    }
+
+    unsafe fn keyboard_resize(&self, w: Window, dx: c_int, dy: c_int) {
+        let client = &self.clients[&w];
+        let geometry = self.geometry(client.frame);
+        let step = self.config.keyboard_step;
+
+        let size = client.constrain_size(
+            geometry.width + dx * step,
+            geometry.height - client.title_height + dy * step,
+        );
+        self.set_frame_geometry(
+            w,
+            Geometry {
+                width: size.0,
+                height: size.1 + client.title_height,
+                ..geometry
+            },
+        );
+        self.discard_enter_events();
+    }
+
+    unsafe fn dump_state(&self) {
+        info!(
+            "Workspace {} ({}), layout {:?}, master ratio {:.2}, focused {:?}",
+            self.current_workspace + 1,
+            self.workspace_name(self.current_workspace),
+            self.layout(),
+            self.master_ratios[self.current_workspace],
+            self.focused
+        );
+        for (i, windows) in self.workspaces.iter().enumerate() {
+            if !windows.is_empty() {
+                info!("Workspace {}: {:?}", i + 1, windows);
+            }
+        }
+        for monitor in &self.monitors {
+            info!("Monitor {}: {:?}", monitor.name, monitor.geometry);
+        }
+
+        let mut windows: Vec<&Window> = self.clients.keys().collect();
+        windows.sort_unstable();
+        for &w in windows {
+            let client = &self.clients[&w];
+            let flags = [
+                (client.floating, "floating"),
+                (client.sticky, "sticky"),
+                (client.above, "above"),
+                (client.below, "below"),
+                (client.saved_geometry.is_some(), "maximized"),
+                (client.snapped.is_some(), "snapped"),
+                (client.fullscreen.is_some(), "fullscreen"),
+                (self.minimized.contains(&w), "minimized"),
+            ];
+            let flags: Vec<&str> = flags
+                .iter()
+                .filter(|&&(set, _)| set)
+                .map(|&(_, flag)| flag)
+                .collect();
+            info!(
+                "Window {} [{}] \"{}\" class {:?} {:?} workspace {} {:?} border {} {:?}",
+                w,
+                client.frame,
+                client.title,
+                client.class,
+                client.window_type,
+                client.workspace + 1,
+                self.geometry(client.frame),
+                client.border_width,
+                flags
+            );
+        }
+    }
+
+    unsafe fn run_command(&mut self, command: &str) -> Result<(), String> {
+        match Action::parse(command) {
+            Some(action) => {
+                info!("IPC command: \"{}\"", command);
+                self.run_action(action);
+                Ok(())
+            }
+            None => Err(format!("unknown command \"{}\"", command)),
+        }
+    }
+
+    unsafe fn key_release(&mut self, e: &XKeyEvent) {
+        let keysym = XLookupKeysym(e as *const XKeyEvent as *mut XKeyEvent, 0) as c_uint;
+        if !(x11::keysym::XK_Shift_L..=x11::keysym::XK_Hyper_R).contains(&keysym) {
+            return;
+        }
+
+        self.end_cycle();
+    }
+
+    // Releasing the modifier commits the window selected by cycling.
+    unsafe fn end_cycle(&mut self) {
+        if let Some(index) = self.cycle_index.take() {
+            XUngrabKeyboard(self.display, CurrentTime);
+            self.hide_cycle_overlay();
+            if index < self.focus_stack.len() {
+                let w = self.focus_stack.remove(index);
+                self.focus_stack.insert(0, w);
+            }
+        }
+    }
+
+    unsafe fn cycle_focus(&mut self) {
+        let len = self.focus_stack.len();
+        let start = self.cycle_index.unwrap_or(0);
+        let index = match (1..=len)
+            .map(|i| (start + i) % len)
+            .find(|&i| self.is_visible(self.focus_stack[i]))
+        {
+            Some(index) => index,
+            None => return,
+        };
+
+        if self.cycle_index.is_none() {
+            XGrabKeyboard(
+                self.display,
+                self.root,
+                0,
+                GrabModeAsync,
+                GrabModeAsync,
+                CurrentTime,
+            );
+        }
+        self.cycle_index = Some(index);
+
+        let w = self.focus_stack[index];
+        self.focus(w);
+        self.warp_pointer(w);
+        self.show_cycle_overlay();
+
+        // Without the modifier held, e.g. from IPC or when released before the grab, no
+        // KeyRelease would end the cycle.
+        let (_, _, state) = self.query_pointer();
+        if state & self.config.mod_key.mask() == 0 {
+            self.end_cycle();
+        }
+    }
+
+    fn cycle_candidates(&self) -> Vec<(usize, Window)> {
+        self.focus_stack
+            .iter()
+            .copied()
+            .enumerate()
+            .filter(|&(_, w)| self.is_visible(w))
+            .collect()
+    }
+
+    unsafe fn show_cycle_overlay(&mut self) {
+        let candidates = self.cycle_candidates();
+        let text_width = candidates
+            .iter()
+            .map(|(_, w)| {
+                let title = &self.clients[w].title;
+                if self.font.is_null() {
+                    0
+                } else {
+                    XTextWidth(
+                        self.font,
+                        title.as_ptr() as *const c_char,
+                        title.len() as c_int,
+                    )
+                }
+            })
+            .max()
+            .unwrap_or(0);
+        let monitor = self.current_monitor();
+        let width = min(max(text_width + 2 * OVERLAY_PADDING, 200), monitor.width);
+        let height = min(candidates.len() as c_int * TITLE_HEIGHT, monitor.height);
+
+        if self.cycle_overlay == 0 {
+            let mut attrs: XSetWindowAttributes = zeroed();
+            attrs.override_redirect = 1;
+            attrs.background_pixel = self.config.background_color.0;
+            attrs.border_pixel = self.config.focused_border_color.0;
+            attrs.event_mask = ExposureMask;
+            self.cycle_overlay = XCreateWindow(
+                self.display,
+                self.root,
+                0,
+                0,
+                1,
+                1,
+                self.config.border_width,
+                CopyFromParent,
+                InputOutput as c_uint,
+                null_mut(),
+                CWOverrideRedirect | CWBackPixel | CWBorderPixel | CWEventMask,
+                &mut attrs,
+            );
+            self.own_windows.insert(self.cycle_overlay);
+        }
+        XMoveResizeWindow(
+            self.display,
+            self.cycle_overlay,
+            monitor.x + (monitor.width - width) / 2,
+            monitor.y + (monitor.height - height) / 2,
+            max(width, 1) as c_uint,
+            max(height, 1) as c_uint,
+        );
+        XMapRaised(self.display, self.cycle_overlay);
+        self.draw_cycle_overlay();
+    }
+
+    // One title per row, the current candidate highlighted in the focused border color.
+    unsafe fn draw_cycle_overlay(&self) {
+        XClearWindow(self.display, self.cycle_overlay);
+        let width = self.geometry(self.cycle_overlay).width;
+        for (row, (index, w)) in self.cycle_candidates().into_iter().enumerate() {
+            let y = row as c_int * TITLE_HEIGHT;
+            if self.cycle_index == Some(index) {
+                XSetForeground(self.display, self.gc, self.config.focused_border_color.0);
+                XFillRectangle(
+                    self.display,
+                    self.cycle_overlay,
+                    self.gc,
+                    0,
+                    y,
+                    width as c_uint,
+                    TITLE_HEIGHT as c_uint,
+                );
+                XSetForeground(self.display, self.gc, TITLE_COLOR);
+            }
+            if self.font.is_null() {
+                continue;
+            }
+
+            let title = &self.clients[&w].title;
+            let (ascent, descent) = ((*self.font).ascent, (*self.font).descent);
+            XDrawString(
+                self.display,
+                self.cycle_overlay,
+                self.gc,
+                OVERLAY_PADDING,
+                y + (TITLE_HEIGHT - ascent - descent) / 2 + ascent,
+                title.as_ptr() as *const c_char,
+                title.len() as c_int,
+            );
+        }
+    }
+
+    unsafe fn hide_cycle_overlay(&mut self) {
+        if self.cycle_overlay != 0 {
+            XDestroyWindow(self.display, self.cycle_overlay);
+            self.own_windows.remove(&self.cycle_overlay);
+            self.cycle_overlay = 0;
+        }
+    }
+
+    unsafe fn warp_pointer(&self, w: Window) {
+        if !self.config.warp_pointer || !self.clients.contains_key(&w) {
+            return;
+        }
+
+        // Warping into the frame under the pointer would only produce a redundant EnterNotify.
+        let geometry = self.geometry(self.clients[&w].frame);
+        let (x, y, _) = self.query_pointer();
+        if geometry.contains(x, y) {
+            return;
+        }
+        XWarpPointer(
+            self.display,
+            0,
+            self.root,
+            0,
+            0,
+            0,
+            0,
+            geometry.x + geometry.width / 2,
+            geometry.y + geometry.height / 2,
+        );
+    }
+
+    unsafe fn switch_workspace(&mut self, target: usize) {
+        if target == self.current_workspace || target >= self.workspaces.len() {
+            return;
+        }
+
+        for w in &self.workspaces[self.current_workspace] {
+            if !self.clients[w].sticky {
+                XUnmapWindow(self.display, self.clients[w].frame);
+            }
+        }
+        for w in &self.workspaces[target] {
+            if !self.minimized.contains(w) && !self.clients[w].sticky {
+                XMapWindow(self.display, self.clients[w].frame);
+            }
+        }
+        self.current_workspace = target;
+        self.set_cardinal(self.root, self.net_current_desktop, target as c_ulong);
+        info!("Switch to workspace: {}", self.workspace_name(target));
+
+        self.retile();
+        self.restack();
+
+        self.focus_top();
+        if let Some(w) = self.focused {
+            self.warp_pointer(w);
+        }
+    }
+
+    unsafe fn move_to_workspace(&mut self, w: Window, target: usize) {
+        if target >= self.workspaces.len()
+            || self
+                .clients
+                .get(&w)
+                .is_none_or(|client| client.sticky || client.workspace == target)
+        {
+            return;
+        }
+
+        let client = self.clients.get_mut(&w).unwrap();
+        self.workspaces[client.workspace].retain(|&c| c != w);
+        self.workspaces[target].push(w);
+        client.workspace = target;
+        // Pagers may also move windows from other workspaces to the current one.
+        if target != self.current_workspace {
+            XUnmapWindow(self.display, client.frame);
+        } else if !self.minimized.contains(&w) {
+            XMapWindow(self.display, client.frame);
+        }
+        self.update_wm_desktop(w);
+        info!(
+            "Move window {} to workspace: {}",
+            w,
+            self.workspace_name(target)
+        );
+
+        self.retile();
+
+        if self.focused == Some(w) {
+            self.focus_top();
+        }
+    }
+
+    fn layout(&self) -> Layout {
+        self.layouts[self.current_workspace]
+    }
+
+    unsafe fn set_layout(&mut self, layout: Layout) {
+        self.layouts[self.current_workspace] = layout;
+        info!(
+            "Layout of workspace {}: {:?}",
+            self.current_workspace + 1,
+            layout
+        );
+        self.retile();
+        self.restack();
+    }
+
+    unsafe fn toggle_layout(&mut self) {
+        self.set_layout(match self.layout() {
+            Layout::Floating => Layout::Tiling,
+            _ => Layout::Floating,
+        });
+    }
+
+    unsafe fn cycle_layout(&mut self) {
+        self.set_layout(match self.layout() {
+            Layout::Floating => Layout::Tiling,
+            Layout::Tiling => Layout::Monocle,
+            Layout::Monocle => Layout::Grid,
+            Layout::Grid => Layout::Floating,
+        });
+    }
+
+    unsafe fn adjust_master_ratio(&mut self, delta: f32) {
+        let ratio = &mut self.master_ratios[self.current_workspace];
+        *ratio = (*ratio + delta).clamp(0.1, 0.9);
+        self.retile();
+    }
+
+    // Compositors read `_NET_WM_WINDOW_OPACITY` from the frame, the top-level window.
+    unsafe fn adjust_opacity(&mut self, w: Window, delta: f32) {
+        let client = match self.clients.get_mut(&w) {
+            Some(client) => client,
+            None => return,
+        };
+        client.opacity = (client.opacity + delta).clamp(MIN_OPACITY, 1.0);
+        let (frame, opacity) = (client.frame, client.opacity);
+
+        if opacity >= 1.0 {
+            XDeleteProperty(self.display, frame, self.net_wm_window_opacity);
+        } else {
+            let value = (opacity as f64 * 0xffffffffu32 as f64) as c_ulong;
+            self.set_cardinal(frame, self.net_wm_window_opacity, value);
+        }
+        info!("Set opacity of window {} to {:.2}", w, opacity);
+    }
+
+    unsafe fn toggle_floating(&mut self, w: Window) {
+        let client = self.clients.get_mut(&w).unwrap();
+        client.floating = !client.floating;
+        info!("Window {} floating: {}", w, client.floating);
+
+        self.retile();
+        self.restack();
+    }
+
+    unsafe fn set_borderless(&mut self, w: Window, borderless: bool) {
+        let (frame, old_border_width) = match self.clients.get(&w) {
+            Some(client) => (client.frame, client.border_width as c_int),
+            None => return,
+        };
+        let geometry = self.geometry(frame);
+
+        let client = self.clients.get_mut(&w).unwrap();
+        client.borderless = borderless;
+        client.border_width = if client.borderless {
+            0
+        } else {
+            self.config.border_width
+        };
+        let (border_width, fullscreen) = (client.border_width, client.fullscreen.is_some());
+        info!("Set border width of window {} to {}", w, border_width);
+        self.update_frame_extents(w);
+        if fullscreen {
+            return;
+        }
+
+        // Keep the outer size, so the client takes over the space of the border.
+        XSetWindowBorderWidth(self.display, frame, border_width);
+        self.move_resize(
+            w,
+            geometry.x,
+            geometry.y,
+            geometry.width + 2 * old_border_width,
+            geometry.height + 2 * old_border_width,
+        );
+        self.retile();
+    }
+
+    // Drop or restore the title bar and border, keeping the outer size of the frame. A rule setting
+    // `border` still decides the border.
+    unsafe fn set_undecorated(&mut self, w: Window, undecorated: bool) {
+        let class = self.fetch_class(w);
+        let title = self.fetch_title(w);
+        let borderless = self
+            .config
+            .border_rule(class.as_deref(), &title)
+            .map_or(undecorated, |border| !border);
+
+        let client = self.clients.get_mut(&w).unwrap();
+        client.title_height = if undecorated { 0 } else { TITLE_HEIGHT };
+        info!(
+            "Set title height of window {} to {}",
+            w, client.title_height
+        );
+        if client.borderless != borderless {
+            self.set_borderless(w, borderless);
+            return;
+        }
+
+        let (frame, fullscreen) = (client.frame, client.fullscreen.is_some());
+        self.update_frame_extents(w);
+        if !fullscreen {
+            self.set_frame_geometry(w, self.geometry(frame));
+            self.draw_title(w);
+        }
+    }
+
+    // Whether `_MOTIF_WM_HINTS` asks for no decorations, as client-side decorated windows do.
+    unsafe fn motif_undecorated(&self, w: Window) -> bool {
+        let hints = self.get_property(w, self.motif_wm_hints, self.motif_wm_hints);
+        hints.len() >= 3 && hints[0] & MWM_HINTS_DECORATIONS != 0 && hints[2] == 0
+    }
+
+    fn is_tiled(&self, w: Window) -> bool {
+        let client = &self.clients[&w];
+        self.layouts[client.workspace] != Layout::Floating
+            && !client.floating
+            && !client.sticky
+            && client.fullscreen.is_none()
+            && !self.minimized.contains(&w)
+    }
+
+    unsafe fn swap_master(&mut self, w: Window) {
+        if self.layout() != Layout::Tiling || !self.is_tiled(w) {
+            return;
+        }
+
+        let workspace = &self.workspaces[self.current_workspace];
+        let index = workspace.iter().position(|&c| c == w).unwrap();
+        let master = workspace.iter().position(|&c| self.is_tiled(c)).unwrap();
+        if index == master {
+            return;
+        }
+        self.workspaces[self.current_workspace].swap(index, master);
+        self.retile();
+    }
+
+    unsafe fn retile(&self) {
+        let windows: Vec<Window> = self.workspaces[self.current_workspace]
+            .iter()
+            .copied()
+            .filter(|&w| self.is_tiled(w))
+            .collect();
+
+        // Monocle shows only the focused tiled window, or the one focused last, so that nothing
+        // shows through it and raising another window cannot uncover the rest.
+        let shown = if self.layout() == Layout::Monocle {
+            self.focused
+                .filter(|w| windows.contains(w))
+                .or_else(|| {
+                    self.focus_stack
+                        .iter()
+                        .copied()
+                        .find(|w| windows.contains(w))
+                })
+                .or_else(|| windows.first().copied())
+        } else {
+            None
+        };
+        for (&w, client) in &self.clients {
+            if !self.is_visible(w) {
+                continue;
+            }
+            if shown.is_some() && shown != Some(w) && windows.contains(&w) {
+                XUnmapWindow(self.display, client.frame);
+            } else {
+                XMapWindow(self.display, client.frame);
+            }
+        }
+
+        if self.layout() == Layout::Floating || windows.is_empty() {
+            return;
+        }
+
+        let area = self.usable_area(self.current_monitor());
+        let gap = self.config.gap;
+        let tile = |w, x, y, width, height| {
+            self.move_resize(w, x + gap, y + gap, width - 2 * gap, height - 2 * gap)
+        };
+
+        match self.layout() {
+            Layout::Floating => (),
+            Layout::Tiling => {
+                let stack_count = windows.len() as c_int - 1;
+                let master_width = if stack_count > 0 {
+                    (area.width as f32 * self.master_ratios[self.current_workspace]) as c_int
+                } else {
+                    area.width
+                };
+
+                for (i, &w) in windows.iter().enumerate() {
+                    if i == 0 {
+                        tile(w, area.x, area.y, master_width, area.height);
+                    } else {
+                        let stack_height = area.height / stack_count;
+                        tile(
+                            w,
+                            area.x + master_width,
+                            area.y + (i as c_int - 1) * stack_height,
+                            area.width - master_width,
+                            stack_height,
+                        );
+                    }
+                }
+            }
+            Layout::Monocle => {
+                if let Some(w) = shown {
+                    tile(w, area.x, area.y, area.width, area.height);
+                }
+            }
+            Layout::Grid => {
+                let count = windows.len() as c_int;
+                let columns = (1..=count).find(|c| c * c >= count).unwrap();
+                let rows = (count + columns - 1) / columns;
+                let (cell_width, cell_height) = (area.width / columns, area.height / rows);
+
+                for (i, &w) in windows.iter().enumerate() {
+                    let (row, column) = (i as c_int / columns, i as c_int % columns);
+                    tile(
+                        w,
+                        area.x + column * cell_width,
+                        area.y + row * cell_height,
+                        cell_width,
+                        cell_height,
+                    );
+                }
+            }
+        }
+        self.discard_enter_events();
+    }
+
+    unsafe fn set_fullscreen(&mut self, w: Window, fullscreen: bool) {
+        let client = &self.clients[&w];
+        let (frame, border_width) = (client.frame, client.border_width);
+        if fullscreen == client.fullscreen.is_some() {
+            return;
+        }
+
+        if fullscreen {
+            let saved = self.geometry(frame);
+            let monitor = self.monitor_of(w);
+            self.clients.get_mut(&w).unwrap().fullscreen = Some(saved);
+            XSetWindowBorderWidth(self.display, frame, 0);
+            XMoveResizeWindow(
+                self.display,
+                frame,
+                monitor.x,
+                monitor.y,
+                monitor.width as c_uint,
+                monitor.height as c_uint,
+            );
+            XMoveResizeWindow(
+                self.display,
+                w,
+                0,
+                0,
+                monitor.width as c_uint,
+                monitor.height as c_uint,
+            );
+            self.update_net_wm_state(w);
+            self.raise(w);
+            XRaiseWindow(self.display, frame);
+            self.update_frame_extents(w);
+            info!("Fullscreen window: {}", w);
+        } else {
+            let saved = self.clients.get_mut(&w).unwrap().fullscreen.take().unwrap();
+            XSetWindowBorderWidth(self.display, frame, border_width);
+            self.set_frame_geometry(w, saved);
+            self.update_net_wm_state(w);
+            self.update_frame_extents(w);
+            info!("Leave fullscreen: {}", w);
+            self.restack();
+            self.retile();
+        }
+    }
+
+    unsafe fn toggle_maximize(&mut self, w: Window) {
+        let frame = match self.clients.get(&w) {
+            Some(client) => client.frame,
+            None => return,
+        };
+
+        match self.clients.get_mut(&w).unwrap().saved_geometry.take() {
+            Some(saved) => {
+                self.set_frame_geometry(w, saved);
+                self.discard_enter_events();
+            }
+            None => {
+                let saved = self.geometry(frame);
+                self.clients.get_mut(&w).unwrap().saved_geometry = Some(saved);
+                let area = self.usable_area(self.monitor_of(w));
+                self.move_resize(w, area.x, area.y, area.width, area.height);
+                self.raise(w);
+            }
+        }
+        self.update_net_wm_state(w);
+    }
+
+    unsafe fn move_resize(&self, w: Window, x: c_int, y: c_int, width: c_int, height: c_int) {
+        let border_width = self.clients[&w].border_width as c_int;
+        self.set_frame_geometry(
+            w,
+            Geometry {
+                x,
+                y,
+                width: width - 2 * border_width,
+                height: height - 2 * border_width,
+            },
+        );
+    }
+
+    // The frame border lies outside its size, so the client fills the frame below the title.
+    unsafe fn set_frame_geometry(&self, w: Window, geometry: Geometry) {
+        let title_height = self.clients[&w].title_height;
+        let frame_size = (
+            max(geometry.width, 1) as c_uint,
+            max(geometry.height, title_height + 1) as c_uint,
+        );
+        XMoveResizeWindow(
+            self.display,
+            self.clients[&w].frame,
+            geometry.x,
+            geometry.y,
+            frame_size.0,
+            frame_size.1,
+        );
+        XMoveResizeWindow(
+            self.display,
+            w,
+            0,
+            title_height,
+            frame_size.0,
+            frame_size.1 - title_height as c_uint,
+        );
+    }
+
+    fn is_visible(&self, w: Window) -> bool {
+        (self.clients[&w].sticky || self.workspaces[self.current_workspace].contains(&w))
+            && !self.minimized.contains(&w)
+    }
+
+    unsafe fn update_net_wm_state(&self, w: Window) {
+        let client = &self.clients[&w];
+        let mut states = Vec::new();
+        if client.fullscreen.is_some() {
+            states.push(self.net_wm_state_fullscreen);
+        }
+        if client.above {
+            states.push(self.net_wm_state_above);
+        }
+        if client.below {
+            states.push(self.net_wm_state_below);
+        }
+        if client.sticky {
+            states.push(self.net_wm_state_sticky);
+        }
+        if client.saved_geometry.is_some() {
+            states.push(self.net_wm_state_maximized_vert);
+            states.push(self.net_wm_state_maximized_horz);
+        }
+        if client.urgent {
+            states.push(self.net_wm_state_demands_attention);
+        }
+        XChangeProperty(
+            self.display,
+            w,
+            self.net_wm_state,
+            XA_ATOM,
+            32,
+            PropModeReplace,
+            states.as_ptr() as *const u8,
+            states.len() as c_int,
+        );
+    }
+
+    // Left, right, top and bottom decoration sizes, all zero while fullscreen.
+    unsafe fn update_frame_extents(&self, w: Window) {
+        let client = &self.clients[&w];
+        let extents: [c_ulong; 4] = if client.fullscreen.is_some() {
+            [0; 4]
+        } else {
+            let border_width = client.border_width as c_ulong;
+            [
+                border_width,
+                border_width,
+                border_width + client.title_height as c_ulong,
+                border_width,
+            ]
+        };
+        XChangeProperty(
+            self.display,
+            w,
+            self.net_frame_extents,
+            XA_CARDINAL,
+            32,
+            PropModeReplace,
+            extents.as_ptr() as *const u8,
+            4,
+        );
+    }
+
+    unsafe fn set_wm_state(&self, w: Window, state: c_int) {
+        let data: [c_ulong; 2] = [state as c_ulong, 0];
+        XChangeProperty(
+            self.display,
+            w,
+            self.wm_state,
+            self.wm_state,
+            32,
+            PropModeReplace,
+            data.as_ptr() as *const u8,
+            2,
+        );
+    }
+
+    unsafe fn minimize(&mut self, w: Window) {
+        if !self.clients.contains_key(&w) || !self.minimized.insert(w) {
+            return;
+        }
+
+        let client = self.clients.get_mut(&w).unwrap();
+        client.ignore_unmaps += 1;
+        XUnmapWindow(self.display, client.frame);
+        XUnmapWindow(self.display, w);
+        self.set_wm_state(w, ICONIC_STATE);
+        info!("Minimize window: {}", w);
+
+        self.retile();
+
+        if self.focused == Some(w) {
+            self.focus_top();
+        }
+    }
+
+    unsafe fn restore(&mut self, w: Window) {
+        if !self.minimized.remove(&w) {
+            return;
+        }
+
+        XMapWindow(self.display, w);
+        if self.clients[&w].workspace == self.current_workspace || self.clients[&w].sticky {
+            XMapWindow(self.display, self.clients[&w].frame);
+        }
+        self.set_wm_state(w, NORMAL_STATE);
+        info!("Restore window: {}", w);
+
+        self.retile();
+    }
+
+    unsafe fn focus_top(&mut self) {
+        match self.focus_stack.iter().find(|&&w| self.is_visible(w)) {
+            Some(&w) => self.focus(w),
+            None => {
+                self.focused = None;
+                self.set_active_window(0);
+                XSetInputFocus(
+                    self.display,
+                    PointerRoot as Window,
+                    RevertToPointerRoot,
+                    CurrentTime,
+                );
+            }
+        }
+    }
+
+    unsafe fn supports_protocol(&self, w: Window, protocol: Atom) -> bool {
+        let mut protocols = null_mut();
+        let mut num_protocols = 0;
+        let mut supported = false;
+        if XGetWMProtocols(self.display, w, &mut protocols, &mut num_protocols) != 0 {
+            supported =
+                slice::from_raw_parts(protocols, num_protocols as usize).contains(&protocol);
+            XFree(protocols as *mut c_void);
+        }
+        supported
+    }
+
+    unsafe fn send_protocol(&self, w: Window, protocol: Atom) {
+        let mut data = ClientMessageData::new();
+        data.set_long(0, protocol as c_long);
+        data.set_long(1, CurrentTime as c_long);
+        let mut msg = XEvent::from(XClientMessageEvent {
+            type_: ClientMessage,
+            serial: 0,
+            send_event: 1,
+            display: self.display,
+            window: w,
+            message_type: self.wm_protocols,
+            format: 32,
+            data,
+        });
+        XSendEvent(self.display, w, 0, NoEventMask, &mut msg);
+    }
+
+    // ICCCM input models: clients with `WM_HINTS.input` false only get focus by `WM_TAKE_FOCUS`.
+    unsafe fn accepts_input(&self, w: Window) -> bool {
+        let hints = XGetWMHints(self.display, w);
+        if hints.is_null() {
+            return true;
+        }
+        let input = (*hints).flags & InputHint == 0 || (*hints).input != 0;
+        XFree(hints as *mut c_void);
+        input
+    }
+
+    unsafe fn close(&self, w: Window) {
+        if !self.clients.contains_key(&w) {
+            return;
+        }
+
+        if self.supports_protocol(w, self.wm_delete_window) {
+            self.send_protocol(w, self.wm_delete_window);
+            info!("Close window: {}", w);
+        } else {
+            XKillClient(self.display, w);
+            info!("Kill client: {}", w);
+        }
+    }
+
+    // For hung clients that ignore `WM_DELETE_WINDOW`.
+    unsafe fn kill(&self, w: Window) {
+        if !self.clients.contains_key(&w) {
+            return;
+        }
+
+        XKillClient(self.display, w);
+        info!("Force kill client: {}", w);
+    }
+
+    unsafe fn snap_position(&self, w: Window, pos: (c_int, c_int)) -> (c_int, c_int) {
+        let border_width = self.clients[&w].border_width as c_int;
+        let size = (
+            self.drag.start_frame_size.0 + 2 * border_width,
+            self.drag.start_frame_size.1 + 2 * border_width,
+        );
+        let (edges_x, edges_y) = self.snap_edges(w, (pos.0 + size.0 / 2, pos.1 + size.1 / 2));
+        let threshold = self.config.snap_threshold;
+        (
+            snap_to_edges(pos.0, size.0, &edges_x, threshold),
+            snap_to_edges(pos.1, size.1, &edges_y, threshold),
+        )
+    }
+
+    // Snap the right and bottom edges of a frame resized from the drag start position.
+    unsafe fn snap_size(&self, w: Window, size: (c_int, c_int)) -> (c_int, c_int) {
+        let border_width = self.clients[&w].border_width as c_int;
+        let pos = self.drag.start_frame_pos;
+        let (right, bottom) = (
+            pos.0 + size.0 + 2 * border_width,
+            pos.1 + size.1 + 2 * border_width,
+        );
+        let (edges_x, edges_y) = self.snap_edges(w, (right, bottom));
+        let threshold = self.config.snap_threshold;
+        (
+            snap_to_edges(right, 0, &edges_x, threshold) - pos.0 - 2 * border_width,
+            snap_to_edges(bottom, 0, &edges_y, threshold) - pos.1 - 2 * border_width,
+        )
+    }
+
+    // Edges of the monitor at `point`, its usable area and the other windows on the workspace.
+    unsafe fn snap_edges(&self, w: Window, point: (c_int, c_int)) -> (Vec<c_int>, Vec<c_int>) {
+        let monitor = self.monitor_at(point.0, point.1);
+        let area = self.usable_area(monitor);
+
+        let mut edges_x = vec![
+            monitor.x,
+            monitor.x + monitor.width,
+            area.x,
+            area.x + area.width,
+        ];
+        let mut edges_y = vec![
+            monitor.y,
+            monitor.y + monitor.height,
+            area.y,
+            area.y + area.height,
+        ];
+        for other in &self.workspaces[self.current_workspace] {
+            if *other == w || !self.is_visible(*other) {
+                continue;
+            }
+            let client = &self.clients[other];
+            let geometry = client.geometry;
+            let border_width = client.border_width as c_int;
+            edges_x.push(geometry.x);
+            edges_x.push(geometry.x + geometry.width + 2 * border_width);
+            edges_y.push(geometry.y);
+            edges_y.push(geometry.y + geometry.height + 2 * border_width);
+        }
+        (edges_x, edges_y)
+    }
+
+    // A fast drag queues far more motion events than can be handled, each of them moving the
+    // frame, so the frame would lag behind the pointer. Only the last of the motion events queued
+    // in a row for the same window is dispatched, which keeps the order of all other events.
+    unsafe fn latest_motion(&self, e: &XMotionEvent) -> XMotionEvent {
+        let mut latest = *e;
+        let mut next: XEvent = zeroed();
+        let mut dropped = 0;
+        while XEventsQueued(self.display, QUEUED_AFTER_READING) > 0 {
+            XPeekEvent(self.display, &mut next);
+            if next.get_type() != MotionNotify || next.motion.window != e.window {
+                break;
+            }
+            XNextEvent(self.display, &mut next);
+            latest = next.motion;
+            dropped += 1;
+        }
+        if dropped > 0 {
+            debug!("Dropped {} queued motion events of {}", dropped, e.window);
+        }
+        latest
+    }
+
+    unsafe fn motion_notify(&mut self, e: &XMotionEvent) {
+        if self.menu != 0 && e.window == self.menu {
+            self.menu_motion(e);
+            return;
+        }
+
+        let w = match self.client_of(e.window) {
+            Some(w) => w,
+            None => return,
+        };
+        if self.drag.window != Some(w) || self.drag.button == 0 {
+            return;
+        }
+
+        if !self.drag.moved {
+            self.drag.moved = true;
+            let client = &self.clients[&w];
+            let restore = client.saved_geometry.is_some() || client.snapped.is_some();
+            if client.saved_geometry.is_some() {
+                self.toggle_maximize(w);
+            }
+            if let Some(snapped) = self.clients.get_mut(&w).unwrap().snapped.take() {
+                self.set_frame_geometry(w, snapped);
+            }
+            if restore {
+                let geometry = self.geometry(self.clients[&w].frame);
+                self.drag.start_frame_size = (geometry.width, geometry.height);
+                self.drag.start_frame_pos = if self.drag.button == Button1 {
+                    (e.x_root - geometry.width / 2, e.y_root - TITLE_HEIGHT / 2)
+                } else {
+                    (geometry.x, geometry.y)
+                };
+                self.drag.start_pos = (e.x_root, e.y_root);
+            }
+        }
+
+        let client = &self.clients[&w];
+        let frame = client.frame;
+        let drag_pos = (e.x_root, e.y_root);
+        let delta = (
+            drag_pos.0 - self.drag.start_pos.0,
+            drag_pos.1 - self.drag.start_pos.1,
+        );
+
+        if e.state & Button1Mask != 0 {
+            let mut dest_frame_pos = (
+                self.drag.start_frame_pos.0 + delta.0,
+                self.drag.start_frame_pos.1 + delta.1,
+            );
+            if e.state & ShiftMask == 0 {
+                dest_frame_pos = if self.config.grid_size > 1 {
+                    (
+                        snap_to_grid(dest_frame_pos.0, self.config.grid_size),
+                        snap_to_grid(dest_frame_pos.1, self.config.grid_size),
+                    )
+                } else {
+                    self.snap_position(w, dest_frame_pos)
+                };
+            }
+            XMoveWindow(self.display, frame, dest_frame_pos.0, dest_frame_pos.1);
+        } else if e.state & Button3Mask != 0 {
+            let size_delta = (
+                max(delta.0, -self.drag.start_frame_size.0),
+                max(delta.1, -self.drag.start_frame_size.1),
+            );
+            let mut frame_size = (
+                self.drag.start_frame_size.0 + size_delta.0,
+                self.drag.start_frame_size.1 + size_delta.1,
+            );
+            if e.state & ShiftMask == 0 {
+                if self.config.grid_size > 1 {
+                    frame_size = (
+                        snap_to_grid(frame_size.0, self.config.grid_size),
+                        snap_to_grid(frame_size.1, self.config.grid_size),
+                    );
+                } else if self.config.snap_resize {
+                    frame_size = self.snap_size(w, frame_size);
+                }
+            }
+            let dest_size = client.constrain_size(frame_size.0, frame_size.1 - client.title_height);
+            self.set_frame_geometry(
+                w,
+                Geometry {
+                    x: self.drag.start_frame_pos.0,
+                    y: self.drag.start_frame_pos.1,
+                    width: dest_size.0,
+                    height: dest_size.1 + client.title_height,
+                },
+            );
+        }
+        XFlush(self.display);
+    }
+}
+
+// Reap exited children from a SIGCHLD handler rather than ignoring SIGCHLD, so that nothing is
+// left as a zombie. `spawn()` blocks the signal while it waits for its own child.
+extern "C" fn sigchld(_signal: c_int) {
+    unsafe {
+        let errno = *libc::__errno_location();
+        while libc::waitpid(-1, null_mut(), libc::WNOHANG) > 0 {}
+        *libc::__errno_location() = errno;
+    }
+}
+
+unsafe fn install_sigchld_handler() {
+    let mut action: libc::sigaction = zeroed();
+    action.sa_sigaction = sigchld as extern "C" fn(c_int) as libc::sighandler_t;
+    action.sa_flags = libc::SA_RESTART | libc::SA_NOCLDSTOP;
+    libc::sigemptyset(&mut action.sa_mask);
+    libc::sigaction(libc::SIGCHLD, &action, null_mut());
+    sigchld(libc::SIGCHLD);
+}
+
+// Wake the event loop through a self-pipe, since Xlib retries reads interrupted by signals.
+extern "C" fn handle_signal(signal: c_int) {
+    if signal == libc::SIGHUP {
+        RELOAD.store(true, Ordering::SeqCst);
+    } else {
+        QUIT.store(true, Ordering::SeqCst);
+    }
+    unsafe {
+        let errno = *libc::__errno_location();
+        libc::write(
+            SIGNAL_PIPE.load(Ordering::SeqCst),
+            b"q".as_ptr() as *const c_void,
+            1,
+        );
+        *libc::__errno_location() = errno;
+    }
+}
+
+unsafe fn install_signal_handlers() -> c_int {
+    let mut fds = [-1; 2];
+    if libc::pipe2(fds.as_mut_ptr(), libc::O_CLOEXEC | libc::O_NONBLOCK) != 0 {
+        panic!("`pipe2()` failed: {}", io::Error::last_os_error());
+    }
+    SIGNAL_PIPE.store(fds[1], Ordering::SeqCst);
+
+    let mut action: libc::sigaction = zeroed();
+    action.sa_sigaction = handle_signal as extern "C" fn(c_int) as libc::sighandler_t;
+    action.sa_flags = libc::SA_RESTART;
+    libc::sigemptyset(&mut action.sa_mask);
+    libc::sigaction(libc::SIGTERM, &action, null_mut());
+    libc::sigaction(libc::SIGINT, &action, null_mut());
+    libc::sigaction(libc::SIGHUP, &action, null_mut());
+    fds[0]
+}
+
+fn spawn(cmd: &str) {
+    let mut command = Command::new("sh");
+    command.arg("-c").arg(cmd).stdin(Stdio::null());
+    unsafe {
+        // Fork once more so the program is reparented to init in its own session, leaving only
+        // the short-lived intermediate child for us to reap.
+        command.pre_exec(|| match libc::fork() {
+            -1 => Err(io::Error::last_os_error()),
+            0 => {
+                libc::setsid();
+                Ok(())
+            }
+            _ => libc::_exit(0),
+        });
+    }
+
+    // Otherwise the SIGCHLD handler may reap the intermediate child before `Child::wait()`. The
+    // mask is not inherited, as the child resets it before exec.
+    let mut old_mask = unsafe { zeroed() };
+    unsafe {
+        let mut mask = zeroed();
+        libc::sigemptyset(&mut mask);
+        libc::sigaddset(&mut mask, libc::SIGCHLD);
+        libc::pthread_sigmask(libc::SIG_BLOCK, &mask, &mut old_mask);
+    }
+    match command.spawn() {
+        Ok(mut child) => match child.wait() {
+            Ok(_) => info!("Spawned: \"{}\"", cmd),
+            Err(e) => warn!("Cannot wait for \"{}\": {}", cmd, e),
+        },
+        Err(e) => error!("Failed to spawn \"{}\": {}", cmd, e),
+    }
+    unsafe { libc::pthread_sigmask(libc::SIG_SETMASK, &old_mask, null_mut()) };
+}
+
+fn snap_to_edges(pos: c_int, size: c_int, edges: &[c_int], threshold: c_int) -> c_int {
+    let mut best = (threshold, pos);
+    for &edge in edges {
+        for &candidate in &[edge, edge - size] {
+            let distance = (candidate - pos).abs();
+            if distance < best.0 {
+                best = (distance, candidate);
+            }
+        }
+    }
+    best.1
+}
+
+// Round to the nearest multiple of `grid_size`.
+fn snap_to_grid(value: c_int, grid_size: c_int) -> c_int {
+    (value + grid_size / 2).div_euclid(grid_size) * grid_size
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn size_hints(flags: c_long) -> XSizeHints {
+        let mut hints: XSizeHints = unsafe { zeroed() };
+        hints.flags = flags;
+        hints
+    }
+
+    #[test]
+    fn constrain_size_increments() {
+        let mut hints = size_hints(PResizeInc | PBaseSize);
+        hints.base_width = 4;
+        hints.base_height = 2;
+        hints.width_inc = 10;
+        hints.height_inc = 20;
+        assert_eq!(constrain_size(&hints, 4, 2), (4, 2));
+        assert_eq!(constrain_size(&hints, 13, 21), (4, 2));
+        assert_eq!(constrain_size(&hints, 14, 22), (14, 22));
+        assert_eq!(constrain_size(&hints, 111, 99), (104, 82));
+        // Smaller than the base size is left alone.
+        assert_eq!(constrain_size(&hints, 1, 1), (1, 1));
+    }
+
+    #[test]
+    fn constrain_size_increments_from_min_size() {
+        let mut hints = size_hints(PResizeInc | PMinSize);
+        hints.min_width = 20;
+        hints.min_height = 10;
+        hints.width_inc = 8;
+        hints.height_inc = 16;
+        assert_eq!(constrain_size(&hints, 35, 30), (28, 26));
+        assert_eq!(constrain_size(&hints, 5, 5), (20, 10));
+    }
+
+    #[test]
+    fn constrain_size_limits() {
+        let mut hints = size_hints(PMinSize | PMaxSize);
+        hints.min_width = 100;
+        hints.min_height = 50;
+        hints.max_width = 400;
+        hints.max_height = 0;
+        assert_eq!(constrain_size(&hints, 10, 10), (100, 50));
+        assert_eq!(constrain_size(&hints, 500, 5000), (400, 5000));
+        assert_eq!(constrain_size(&size_hints(0), 7, 9), (7, 9));
+    }
+
+    #[test]
+    fn snap_to_nearest_edge() {
+        let edges = [0, 800];
+        // Either side of the window snaps, whichever is closer.
+        assert_eq!(snap_to_edges(10, 100, &edges, 15), 0);
+        assert_eq!(snap_to_edges(-14, 100, &edges, 15), 0);
+        assert_eq!(snap_to_edges(690, 100, &edges, 15), 700);
+        assert_eq!(snap_to_edges(15, 100, &edges, 15), 15);
+        assert_eq!(snap_to_edges(400, 100, &edges, 15), 400);
+        assert_eq!(snap_to_edges(5, 100, &[0, 8], 15), 8);
+        assert_eq!(snap_to_edges(5, 100, &[], 15), 5);
+    }
+
+    #[test]
+    fn snap_to_nearest_grid_line() {
+        assert_eq!(snap_to_grid(0, 10), 0);
+        assert_eq!(snap_to_grid(4, 10), 0);
+        assert_eq!(snap_to_grid(5, 10), 10);
+        assert_eq!(snap_to_grid(26, 10), 30);
+        assert_eq!(snap_to_grid(-4, 10), 0);
+        assert_eq!(snap_to_grid(-6, 10), -10);
+        assert_eq!(snap_to_grid(7, 1), 7);
+    }
+}
@@ -0,0 +1,71 @@
+use std::collections::HashMap;
+use std::fs;
+use std::os::raw::c_int;
+use std::path::PathBuf;
+
+use log::warn;
+
+use crate::config::Config;
+
+// The last frame geometry `[x, y, width, height]` of each `WM_CLASS` class, kept in
+// `geometry.toml` next to the config file.
+#[derive(Default)]
+pub struct Placements {
+    path: Option<PathBuf>,
+    geometries: HashMap<String, [c_int; 4]>,
+    dirty: bool,
+}
+
+impl Placements {
+    pub fn load() -> Self {
+        let path = Config::dir().map(|dir| dir.join("geometry.toml"));
+        let geometries = path
+            .as_ref()
+            .and_then(|path| {
+                let content = fs::read_to_string(path).ok()?;
+                toml::from_str(&content)
+                    .map_err(|e| warn!("Cannot parse \"{}\": {}", path.display(), e))
+                    .ok()
+            })
+            .unwrap_or_default();
+        Self {
+            path,
+            geometries,
+            dirty: false,
+        }
+    }
+
+    pub fn get(&self, class: &str) -> Option<[c_int; 4]> {
+        self.geometries.get(class).copied()
+    }
+
+    pub fn set(&mut self, class: &str, geometry: [c_int; 4]) {
+        if self.geometries.get(class) != Some(&geometry) {
+            self.geometries.insert(class.to_string(), geometry);
+            self.dirty = true;
+        }
+    }
+
+    pub fn save(&mut self) {
+        let path = match &self.path {
+            Some(path) if self.dirty => path,
+            _ => return,
+        };
+        self.dirty = false;
+
+        let content = match toml::to_string(&self.geometries) {
+            Ok(content) => content,
+            Err(e) => {
+                warn!("Cannot serialize window geometries: {}", e);
+                return;
+            }
+        };
+        let written = match path.parent() {
+            Some(dir) => fs::create_dir_all(dir).and_then(|_| fs::write(path, content)),
+            None => fs::write(path, content),
+        };
+        if let Err(e) = written {
+            warn!("Cannot write \"{}\": {}", path.display(), e);
+        }
+    }
+}
@@ -0,0 +1,284 @@
+// Integration tests driving the window manager inside a nested Xephyr server.
+//
+// They need `Xephyr` in `PATH`, so they are ignored unless asked for:
+//
+//     cargo test --test xephyr -- --ignored
+
+use std::env;
+use std::ffi::CString;
+use std::fs;
+use std::io::{BufRead, BufReader, Write};
+use std::os::raw::{c_int, c_uint, c_ulong};
+use std::os::unix::net::UnixStream;
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, Stdio};
+use std::ptr::null_mut;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use x11::xlib::*;
+
+static NEXT_DISPLAY: AtomicUsize = AtomicUsize::new(90);
+
+struct Session {
+    xephyr: Child,
+    wm: Child,
+    display: *mut Display,
+    name: String,
+    runtime_dir: PathBuf,
+}
+
+impl Session {
+    fn start() -> Self {
+        let number = loop {
+            let number = NEXT_DISPLAY.fetch_add(1, Ordering::SeqCst);
+            if !Path::new(&format!("/tmp/.X11-unix/X{}", number)).exists() {
+                break number;
+            }
+        };
+        let name = format!(":{}", number);
+
+        let xephyr = Command::new("Xephyr")
+            .args([
+                name.as_str(),
+                "-screen",
+                "800x600",
+                "-ac",
+                "-br",
+                "-noreset",
+            ])
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()
+            .expect("failed to start Xephyr");
+        let socket = format!("/tmp/.X11-unix/X{}", number);
+        wait_until(|| Path::new(&socket).exists());
+
+        // Keep the user's config and control socket out of the test.
+        let runtime_dir = env::temp_dir().join(format!("simple-wm-test-{}", number));
+        fs::create_dir_all(&runtime_dir).unwrap();
+        let wm = Command::new(env!("CARGO_BIN_EXE_simple-wm"))
+            .args(["--display", name.as_str()])
+            .env("XDG_RUNTIME_DIR", &runtime_dir)
+            .env("XDG_CONFIG_HOME", &runtime_dir)
+            .spawn()
+            .expect("failed to start simple-wm");
+
+        let display_name = CString::new(name.as_str()).unwrap();
+        let display = unsafe { XOpenDisplay(display_name.as_ptr()) };
+        assert!(!display.is_null(), "cannot open the Xephyr display");
+
+        let session = Self {
+            xephyr,
+            wm,
+            display,
+            name,
+            runtime_dir,
+        };
+        wait_until(|| session.wm_running());
+        session
+    }
+
+    fn wm_running(&self) -> bool {
+        unsafe {
+            let name = CString::new(format!("WM_S{}", XDefaultScreen(self.display))).unwrap();
+            let atom = XInternAtom(self.display, name.as_ptr(), 0);
+            XGetSelectionOwner(self.display, atom) != 0
+        }
+    }
+
+    fn root(&self) -> Window {
+        unsafe { XDefaultRootWindow(self.display) }
+    }
+
+    fn create_window(&self) -> Window {
+        unsafe {
+            let w = XCreateSimpleWindow(self.display, self.root(), 0, 0, 200, 100, 0, 0, 0);
+            XMapWindow(self.display, w);
+            XSync(self.display, 0);
+            w
+        }
+    }
+
+    // Run a command over the IPC socket and return the reply line.
+    fn command(&self, command: &str) -> String {
+        let path = self
+            .runtime_dir
+            .join(format!("simple-wm-{}.sock", self.name));
+        wait_until(|| path.exists());
+        let mut stream = UnixStream::connect(&path).expect("cannot connect to the IPC socket");
+        writeln!(stream, "{}", command).unwrap();
+        let mut reply = String::new();
+        BufReader::new(&stream).read_line(&mut reply).unwrap();
+        reply
+    }
+
+    fn active_window(&self) -> Window {
+        let name = CString::new("_NET_ACTIVE_WINDOW").unwrap();
+        let (mut actual_type, mut actual_format) = (0, 0);
+        let (mut num_items, mut bytes_after) = (0, 0);
+        let mut data = null_mut();
+        unsafe {
+            let atom = XInternAtom(self.display, name.as_ptr(), 0);
+            XGetWindowProperty(
+                self.display,
+                self.root(),
+                atom,
+                0,
+                1,
+                0,
+                XA_WINDOW,
+                &mut actual_type,
+                &mut actual_format,
+                &mut num_items,
+                &mut bytes_after,
+                &mut data,
+            );
+            if data.is_null() {
+                return 0;
+            }
+            let w = if num_items == 1 {
+                *(data as *const c_ulong)
+            } else {
+                0
+            };
+            XFree(data as *mut _);
+            w
+        }
+    }
+
+    // Position and size of the frame around a client, in root coordinates.
+    fn frame_geometry(&self, w: Window) -> (c_int, c_int, c_uint, c_uint) {
+        let (mut root, mut x, mut y) = (0, 0, 0);
+        let (mut width, mut height, mut border_width, mut depth) = (0, 0, 0, 0);
+        unsafe {
+            XSync(self.display, 0);
+            XGetGeometry(
+                self.display,
+                self.parent(w),
+                &mut root,
+                &mut x,
+                &mut y,
+                &mut width,
+                &mut height,
+                &mut border_width,
+                &mut depth,
+            );
+        }
+        (x, y, width, height)
+    }
+
+    fn parent(&self, w: Window) -> Window {
+        let (mut root, mut parent) = (0, 0);
+        let mut children = null_mut();
+        let mut num_children: c_uint = 0;
+        unsafe {
+            XQueryTree(
+                self.display,
+                w,
+                &mut root,
+                &mut parent,
+                &mut children,
+                &mut num_children,
+            );
+            if !children.is_null() {
+                XFree(children as *mut _);
+            }
+        }
+        parent
+    }
+}
+
+impl Drop for Session {
+    fn drop(&mut self) {
+        unsafe { XCloseDisplay(self.display) };
+        let _ = self.wm.kill();
+        let _ = self.wm.wait();
+        let _ = self.xephyr.kill();
+        let _ = self.xephyr.wait();
+        let _ = fs::remove_dir_all(&self.runtime_dir);
+    }
+}
+
+fn wait_until(mut condition: impl FnMut() -> bool) {
+    let deadline = Instant::now() + Duration::from_secs(5);
+    while !condition() {
+        assert!(Instant::now() < deadline, "timed out");
+        thread::sleep(Duration::from_millis(50));
+    }
+}
+
+#[test]
+#[ignore]
+fn frames_mapped_window() {
+    let session = Session::start();
+
+    let w = session.create_window();
+    wait_until(|| session.parent(w) != session.root());
+}
+
+#[test]
+#[ignore]
+fn unframes_unmapped_window() {
+    let session = Session::start();
+
+    let w = session.create_window();
+    wait_until(|| session.parent(w) != session.root());
+
+    unsafe {
+        XUnmapWindow(session.display, w);
+        XSync(session.display, 0);
+    }
+    wait_until(|| session.parent(w) == session.root());
+}
+
+#[test]
+#[ignore]
+fn retiles_window_toggled_back_from_floating() {
+    let session = Session::start();
+
+    let first = session.create_window();
+    let second = session.create_window();
+    wait_until(|| session.parent(first) != session.root());
+    wait_until(|| session.parent(second) != session.root());
+    assert_eq!(session.command("toggle_layout"), "ok\n");
+
+    // Both windows leave their mapped width of 200 once tiled, and the focused one is made
+    // floating.
+    wait_until(|| {
+        session.frame_geometry(first).2 != 200 && session.frame_geometry(second).2 != 200
+    });
+    let floating = session.active_window();
+    assert!(floating == first || floating == second);
+    let tiled = if floating == first { second } else { first };
+    let tiled_geometry = (
+        session.frame_geometry(floating),
+        session.frame_geometry(tiled),
+    );
+
+    assert_eq!(session.command("toggle_floating"), "ok\n");
+    wait_until(|| session.frame_geometry(tiled) != tiled_geometry.1);
+
+    assert_eq!(session.command("toggle_floating"), "ok\n");
+    wait_until(|| {
+        (
+            session.frame_geometry(floating),
+            session.frame_geometry(tiled),
+        ) == tiled_geometry
+    });
+}
+
+#[test]
+#[ignore]
+fn floating_window_resizes_itself() {
+    let session = Session::start();
+
+    let w = session.create_window();
+    wait_until(|| session.parent(w) != session.root());
+    unsafe {
+        XResizeWindow(session.display, w, 300, 150);
+        XSync(session.display, 0);
+    }
+    wait_until(|| session.frame_geometry(w).2 == 300);
+}